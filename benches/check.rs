@@ -0,0 +1,1195 @@
+// host cross-checks for the analyzer's FFT paths: the real-input FFT against
+// the plain complex one, the fixed-point backend against f32, the band
+// levels and the smoothing. Also the in-memory and file stream readers, the
+// ReplayGain tags, the VBR length headers, the mix bus, the player's track
+// chain, the EQ filters, the A-B loop, the speed and pitch changes and the
+// loudness meter.
+// Run with
+//   cargo run --release --manifest-path benches/Cargo.toml --bin check
+// exits non-zero when a path is off by more than its tolerance
+
+#[path = "../src/bands.rs"]
+#[allow(dead_code)]
+mod bands;
+#[path = "../src/dsp/mod.rs"]
+#[allow(dead_code)]
+mod dsp;
+#[path = "../src/error.rs"]
+#[allow(dead_code)]
+mod error;
+#[path = "../src/fft.rs"]
+#[allow(dead_code)]
+mod fft;
+#[path = "../src/fft_fixed.rs"]
+#[allow(dead_code)]
+mod fft_fixed;
+mod host_io;
+#[path = "../src/io.rs"]
+#[allow(dead_code)]
+mod io;
+#[path = "../src/tags.rs"]
+#[allow(dead_code)]
+mod tags;
+#[path = "../src/vbr.rs"]
+#[allow(dead_code)]
+mod vbr;
+
+use std::f32::consts::PI;
+use std::process::ExitCode;
+
+use bands::{Aggregation, BandLayout};
+use dsp::ab_loop::{AbLoop, MAX_LOOP_SECS};
+use dsp::dynamics::{Limiter, Master, MasterSettings};
+use dsp::eq::{
+    Band, Biquad, BiquadState, Eq, EqDesign, EqPreset, FilterKind, GRAPHIC_FREQS, MAX_BANDS,
+};
+use dsp::gain::SmoothGain;
+use dsp::loudness::{LoudnessMeter, k_weighting};
+use dsp::mix::{MixBus, pan_gains};
+use dsp::replaygain::{ReplayGainMode, replaygain_gain};
+use dsp::stretch::Stretch;
+use fft::{Analyzer, AnalyzerSettings, FftBackend, FloatFft, Window};
+use fft_fixed::FixedFft;
+use io::{ByteRead, ByteSeek, SeekFrom, SliceReader};
+use tags::ReplayGain;
+
+const SIZES: [usize; 6] = [16, 512, 1024, 4096, 8192, 16384];
+/// Largest bin error allowed, relative to the largest bin
+const REAL_TOLERANCE: f32 = 1e-5;
+/// Levels the fixed-point backend is tried at, in dBFS
+const FIXED_LEVELS_DB: [f32; 4] = [0.0, -20.0, -60.0, -80.0];
+/// Lowest signal to error ratio of the fixed-point magnitudes
+const FIXED_MIN_SNR_DB: f64 = 90.0;
+/// How far a bin-centered sine may read from its level with no window, in dB
+const WINDOW_GAIN_TOLERANCE_DB: f32 = 0.05;
+const WINDOW_SIZE: usize = 8192;
+/// How far the analyzer's reading of a sine may be from its level, in dB
+const SCALE_TOLERANCE_DB: f32 = 0.1;
+/// Frame rates the smoothing is run at, per second
+const SMOOTHING_RATES: [u32; 3] = [20, 60, 240];
+/// How far bars and trails may end up from the 60 fps ones, in bar heights.
+/// The trails take the bars as moving in a straight line over each frame,
+/// which is only close at 20 fps with a fast attack
+const SMOOTHING_TOLERANCE: f32 = 1e-2;
+/// How far the meter's K-weighting may be from the BS.1770 one, in dB
+const K_WEIGHTING_TOLERANCE_DB: f64 = 0.01;
+
+fn twiddles(n: usize) -> (Vec<f32>, Vec<f32>) {
+    (0..n / 2)
+        .map(|k| {
+            let a = 2.0 * PI * k as f32 / n as f32;
+            (a.cos(), a.sin())
+        })
+        .unzip()
+}
+
+/// Tones, a DC offset and noise, so every bin has something in it
+fn signal(n: usize) -> Vec<f32> {
+    let mut seed = 0x1234_5678u32;
+    (0..n)
+        .map(|i| {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let noise = (seed >> 8) as f32 / (1 << 24) as f32 - 0.5;
+            let t = i as f32 / 44100.0;
+            0.05 + 0.5 * (2.0 * PI * 440.0 * t).sin()
+                + 0.25 * (2.0 * PI * 5000.0 * t).cos()
+                + 0.1 * noise
+        })
+        .collect()
+}
+
+fn to_i16(signal: &[f32], level_db: f32) -> Vec<i16> {
+    let gain = 10f32.powf(level_db / 20.0) * i16::MAX as f32 / 0.9;
+    signal
+        .iter()
+        .map(|&x| (x * gain).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16)
+        .collect()
+}
+
+/// Energy of the f32 magnitudes over the energy of the difference, in dB
+fn snr_db(reference: &[f32], power: &[f32]) -> f64 {
+    let (mut signal, mut noise) = (0.0f64, 0.0f64);
+    for (&r, &p) in reference.iter().zip(power) {
+        let (r, p) = ((r as f64).sqrt(), (p as f64).sqrt());
+        signal += r * r;
+        noise += (r - p) * (r - p);
+    }
+    10.0 * (signal / noise.max(f64::MIN_POSITIVE)).log10()
+}
+
+/// Sine at `level_db` dBFS right on bin `n / 8`
+fn bin_sine(n: usize, level_db: f32) -> Vec<i16> {
+    let amplitude = 10f32.powf(level_db / 20.0) * i16::MAX as f32;
+    (0..n)
+        .map(|i| {
+            let x = (2.0 * PI * (n / 8 * i) as f32 / n as f32).sin();
+            (x * amplitude).round() as i16
+        })
+        .collect()
+}
+
+/// Peak bin of a -6 dBFS sine right on a bin, through backend `B` with `window`
+fn sine_peak_db<B: FftBackend>(window: Window) -> f32 {
+    let samples = bin_sine(WINDOW_SIZE, -6.0);
+    let mut power = vec![0.0; WINDOW_SIZE / 2];
+    B::new(WINDOW_SIZE, window).power(&samples, &mut power);
+    10.0 * power.iter().fold(0.0f32, |a, &b| a.max(b)).log10()
+}
+
+/// Sine at `level_db` dBFS and `hz` at the analyzer's sampling rate
+fn sine(n: usize, hz: f32, level_db: f32, sampling_rate: u32) -> Vec<i16> {
+    let amplitude = 10f32.powf(level_db / 20.0) * i16::MAX as f32;
+    (0..n)
+        .map(|i| {
+            let x = (2.0 * PI * hz * i as f32 / sampling_rate as f32).sin();
+            (x * amplitude).round() as i16
+        })
+        .collect()
+}
+
+/// The same samples on both channels, interleaved like the PCM ring
+fn both(mono: &[i16]) -> Vec<i16> {
+    mono.iter().flat_map(|&s| [s, s]).collect()
+}
+
+/// Bars after a tone for `on_s` then silence until `end_s`, at `fps` frames
+/// per second
+fn smoothed(settings: &AnalyzerSettings, fps: u32, on_s: f32, end_s: f32) -> (Vec<f32>, Vec<f32>) {
+    let tone = both(&sine(settings.size, 1000.0, -6.0, settings.sampling_rate));
+    let silence = vec![0; settings.size * 2];
+    let mut analyzer = Analyzer::<FloatFft>::new(settings);
+    let dt = 1.0 / fps as f32;
+    let mut m = 0;
+    for frame in 0..(end_s * fps as f32).round() as u32 {
+        let on = (frame as f32) < on_s * fps as f32;
+        m = analyzer.analyze(if on { &tone } else { &silence }, dt);
+    }
+    (
+        analyzer.out_smooth[..m].to_vec(),
+        analyzer.out_smear[..m].to_vec(),
+    )
+}
+
+/// Bins 0..n/2 through the full complex FFT
+fn reference(input: &[f32], tw_re: &[f32], tw_im: &[f32]) -> (Vec<f32>, Vec<f32>) {
+    let mut re = input.to_vec();
+    let mut im = vec![0.0; input.len()];
+    fft::fft_inplace(&mut re, &mut im, tw_re, tw_im);
+    re.truncate(input.len() / 2);
+    im.truncate(input.len() / 2);
+    (re, im)
+}
+
+fn max_error(re: &[f32], im: &[f32], ref_re: &[f32], ref_im: &[f32]) -> f32 {
+    let peak = ref_re
+        .iter()
+        .zip(ref_im)
+        .map(|(r, i)| r.hypot(*i))
+        .fold(0.0, f32::max);
+    let err = re
+        .iter()
+        .zip(im)
+        .zip(ref_re.iter().zip(ref_im))
+        .map(|((r, i), (rr, ri))| (r - rr).hypot(i - ri))
+        .fold(0.0, f32::max);
+    err / peak
+}
+
+/// Print a named check's result and pass it on
+fn report(name: &str, pass: bool) -> bool {
+    println!("{name:<56} {}", if pass { "ok" } else { "FAIL" });
+    pass
+}
+
+/// `SliceReader` at its edges, seeking like lseek and reading like read(2)
+fn check_slice_reader() -> bool {
+    let data: Vec<u8> = (0..10).collect();
+    let mut ok = true;
+    let mut buf = [0u8; 8];
+
+    let mut r = SliceReader::new(&data);
+    ok &= report(
+        "io     seek past the end, reads nothing",
+        r.seek(SeekFrom::Start(100)) == Ok(100) && r.read(&mut buf) == Ok(0) && r.size() == Ok(10),
+    );
+    ok &= report(
+        "io     end with a negative offset",
+        r.seek(SeekFrom::End(-3)) == Ok(7) && r.read(&mut buf) == Ok(3) && buf[..3] == [7, 8, 9],
+    );
+    ok &= report(
+        "io     current with a negative offset",
+        r.seek(SeekFrom::Current(-2)) == Ok(8) && r.read(&mut buf[..1]) == Ok(1) && buf[0] == 8,
+    );
+    let before = r.seek(SeekFrom::Current(0));
+    ok &= report(
+        "io     seek before the start fails, position kept",
+        r.seek(SeekFrom::Current(-20)).is_err()
+            && r.seek(SeekFrom::End(-11)).is_err()
+            && r.seek(SeekFrom::Current(0)) == before,
+    );
+
+    let mut r = SliceReader::new(&data);
+    ok &= report(
+        "io     read_full stops at the end",
+        r.seek(SeekFrom::Start(6)) == Ok(6)
+            && r.read_full(&mut buf) == Ok(4)
+            && buf[..4] == [6, 7, 8, 9]
+            && r.read_full(&mut buf) == Ok(0)
+            && r.read_full(&mut []) == Ok(0),
+    );
+
+    // Box forwards only seek, so this is the trait's default size()
+    let mut r: Box<SliceReader> = Box::new(SliceReader::new(&data));
+    ok &= report(
+        "io     default size() leaves the position alone",
+        r.seek(SeekFrom::Start(4)) == Ok(4)
+            && r.size() == Ok(10)
+            && r.seek(SeekFrom::Current(0)) == Ok(4),
+    );
+    ok &= report(
+        "io     empty slice",
+        SliceReader::new(&[]).read(&mut buf) == Ok(0) && SliceReader::new(&[]).size() == Ok(0),
+    );
+    ok
+}
+
+/// The host `File` impl against the same edges, and tags read off a real file
+fn check_file_stream() -> bool {
+    let path = std::env::temp_dir().join(format!("musializer-check-{}", std::process::id()));
+    let data: Vec<u8> = (0..10).collect();
+    let mut ok = true;
+    let mut buf = [0u8; 8];
+
+    let opened = std::fs::write(&path, &data).and_then(|_| std::fs::File::open(&path));
+    let Ok(mut f) = opened else {
+        return report("io     File, temp file", false);
+    };
+    ok &= report(
+        "io     File seek past the end, reads nothing",
+        f.seek(SeekFrom::Start(100)) == Ok(100) && f.read(&mut buf) == Ok(0) && f.size() == Ok(10),
+    );
+    ok &= report(
+        "io     File end with a negative offset",
+        f.seek(SeekFrom::End(-3)) == Ok(7)
+            && f.read_full(&mut buf) == Ok(3)
+            && buf[..3] == [7, 8, 9],
+    );
+    ok &= report(
+        "io     File seek before the start is EINVAL",
+        f.seek(SeekFrom::Current(-20)) == Err(error::Error::Io(error::SCE_ERROR_ERRNO_EINVAL))
+            && f.seek(SeekFrom::Current(0)) == Ok(10),
+    );
+
+    let file = [
+        id3v24(&[(b"TXXX", txxx("REPLAYGAIN_TRACK_GAIN", "-6.50 dB", false))]),
+        vec![0xFFu8; 4096],
+    ]
+    .concat();
+    let rg = std::fs::write(&path, &file)
+        .and_then(|_| std::fs::File::open(&path))
+        .ok()
+        .and_then(|mut f| tags::read_replaygain(&mut f).ok());
+    ok &= report(
+        "io     File through the tag reader",
+        rg.is_some_and(|rg| close(rg.track_gain_db, -6.5)),
+    );
+    let _ = std::fs::remove_file(&path);
+    ok
+}
+
+/// ID3v2.4 tag holding `frames`, each an (id, body) pair
+fn id3v24(frames: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let synchsafe = |n: usize| {
+        [
+            (n >> 21) as u8 & 0x7F,
+            (n >> 14) as u8 & 0x7F,
+            (n >> 7) as u8 & 0x7F,
+            n as u8 & 0x7F,
+        ]
+    };
+    let mut body = Vec::new();
+    for (id, frame) in frames {
+        body.extend_from_slice(*id);
+        body.extend_from_slice(&synchsafe(frame.len()));
+        body.extend_from_slice(&[0, 0]);
+        body.extend_from_slice(frame);
+    }
+    // some padding, as taggers leave
+    body.extend_from_slice(&[0; 16]);
+    let mut tag = b"ID3\x04\x00\x00".to_vec();
+    tag.extend_from_slice(&synchsafe(body.len()));
+    tag.extend(body);
+    tag
+}
+
+/// TXXX body, latin-1 or UTF-16 with a BOM
+fn txxx(key: &str, value: &str, utf16: bool) -> Vec<u8> {
+    if !utf16 {
+        return [&[0u8][..], key.as_bytes(), &[0], value.as_bytes()].concat();
+    }
+    let wide = |s: &str| -> Vec<u8> {
+        let mut out = vec![0xFF, 0xFE];
+        out.extend(s.encode_utf16().flat_map(u16::to_le_bytes));
+        out
+    };
+    [&[1u8][..], &wide(key), &[0, 0], &wide(value)].concat()
+}
+
+/// APEv2 tag with a footer and no header, text items only
+fn apev2(items: &[(&str, &str)]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (key, value) in items {
+        body.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(key.as_bytes());
+        body.push(0);
+        body.extend_from_slice(value.as_bytes());
+    }
+    let mut footer = b"APETAGEX".to_vec();
+    footer.extend_from_slice(&2000u32.to_le_bytes());
+    footer.extend_from_slice(&(body.len() as u32 + 32).to_le_bytes());
+    footer.extend_from_slice(&(items.len() as u32).to_le_bytes());
+    footer.extend_from_slice(&[0; 12]);
+    body.extend(footer);
+    body
+}
+
+/// Vorbis comment block from the vendor length on, as in Ogg and FLAC
+fn vorbis_comments(comments: &[&str]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut push = |s: &[u8]| {
+        out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        out.extend_from_slice(s);
+    };
+    push(b"reference libFLAC 1.4.3");
+    out.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for c in comments {
+        out.extend_from_slice(&(c.len() as u32).to_le_bytes());
+        out.extend_from_slice(c.as_bytes());
+    }
+    out
+}
+
+fn close(a: Option<f32>, b: f32) -> bool {
+    a.is_some_and(|a| (a - b).abs() < 1e-4)
+}
+
+/// ReplayGain from ID3v2, APEv2 and Vorbis comment tags, and the gain worked out from it
+fn check_replaygain() -> bool {
+    let mut ok = true;
+    let audio = vec![0xFFu8; 4096];
+
+    let file = [
+        id3v24(&[
+            (b"TIT2", b"\x00title".to_vec()),
+            (b"TXXX", txxx("REPLAYGAIN_TRACK_GAIN", "-6.50 dB", false)),
+            (b"TXXX", txxx("replaygain_track_peak", "0.988547", true)),
+        ]),
+        audio.clone(),
+    ]
+    .concat();
+    let rg = tags::read_replaygain(&mut SliceReader::new(&file)).unwrap_or_default();
+    ok &= report(
+        "tags   ID3v2.4 TXXX, latin-1 and UTF-16",
+        close(rg.track_gain_db, -6.5) && close(rg.track_peak, 0.988547),
+    );
+
+    // APE in front of an ID3v1 tag, ID3v2 wins where both have a value
+    let file = [
+        id3v24(&[(b"TXXX", txxx("REPLAYGAIN_TRACK_GAIN", "-3 dB", false))]),
+        audio.clone(),
+        apev2(&[
+            ("REPLAYGAIN_TRACK_GAIN", "+1.00 dB"),
+            ("REPLAYGAIN_ALBUM_GAIN", "-2.25 dB"),
+            ("REPLAYGAIN_ALBUM_PEAK", "0.5"),
+        ]),
+        [b"TAG".to_vec(), vec![0; 125]].concat(),
+    ]
+    .concat();
+    let rg = tags::read_replaygain(&mut SliceReader::new(&file)).unwrap_or_default();
+    ok &= report(
+        "tags   APEv2 before ID3v1, merged under ID3v2",
+        close(rg.track_gain_db, -3.0)
+            && close(rg.album_gain_db, -2.25)
+            && close(rg.album_peak, 0.5),
+    );
+
+    let rg = tags::read_replaygain(&mut SliceReader::new(&audio)).unwrap_or_default();
+    ok &= report("tags   untagged file", rg == ReplayGain::default());
+
+    // a long comment (cover art) in the middle gets skipped
+    let art = format!("METADATA_BLOCK_PICTURE={}", "A".repeat(1000));
+    let block = vorbis_comments(&[
+        "TITLE=title",
+        "replaygain_track_gain=-7.10 dB",
+        &art,
+        "REPLAYGAIN_ALBUM_GAIN=+0.50 dB",
+        "REPLAYGAIN_ALBUM_PEAK=0.75",
+    ]);
+    let rg = tags::read_vorbis_comments(&mut SliceReader::new(&block)).unwrap_or_default();
+    ok &= report(
+        "tags   Vorbis comments, past a long one",
+        close(rg.track_gain_db, -7.1) && close(rg.album_gain_db, 0.5) && close(rg.album_peak, 0.75),
+    );
+    // cut off mid-comment, what came before is kept
+    let rg = tags::read_vorbis_comments(&mut SliceReader::new(&block[..block.len() - 10]))
+        .unwrap_or_default();
+    ok &= report(
+        "tags   Vorbis comments cut short",
+        close(rg.track_gain_db, -7.1) && close(rg.album_gain_db, 0.5) && rg.album_peak.is_none(),
+    );
+
+    // +6 dB with a 0.4 track peak fits, an album peak alone doesn't limit it
+    let rg = ReplayGain {
+        track_gain_db: Some(6.0),
+        track_peak: Some(0.4),
+        album_gain_db: None,
+        album_peak: Some(0.9),
+    };
+    let gain = replaygain_gain(&rg, ReplayGainMode::Album);
+    ok &= report(
+        "rg     album falls back to track gain and peak",
+        (gain - 10f32.powf(6.0 / 20.0)).abs() < 1e-4,
+    );
+    let rg = ReplayGain {
+        track_gain_db: Some(6.0),
+        track_peak: Some(0.9),
+        ..rg
+    };
+    let gain = replaygain_gain(&rg, ReplayGainMode::Track);
+    ok &= report(
+        "rg     peak keeps the gain under full scale",
+        (gain * 0.9 - 1.0).abs() < 1e-4,
+    );
+    ok &= report(
+        "rg     off",
+        replaygain_gain(&rg, ReplayGainMode::Off) == 1.0,
+    );
+    ok
+}
+
+/// A 417 byte first mp3 frame with `tag` written `at` bytes in
+fn first_frame(header: [u8; 4], at: usize, tag: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0u8; 417];
+    frame[..4].copy_from_slice(&header);
+    frame[at..at + tag.len()].copy_from_slice(tag);
+    frame
+}
+
+/// Track length from the Xing/Info and VBRI headers
+fn check_vbr() -> bool {
+    const MPEG1_STEREO: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+    const MPEG2_MONO: [u8; 4] = [0xFF, 0xF3, 0x90, 0xC0];
+    let xing = |id: &[u8; 4], flags: u32, frames: u32| {
+        [&id[..], &flags.to_be_bytes(), &frames.to_be_bytes()].concat()
+    };
+    let vbri = [
+        &b"VBRI"[..],
+        &1u16.to_be_bytes(),
+        &0u16.to_be_bytes(),
+        &75u16.to_be_bytes(),
+        &4_000_000u32.to_be_bytes(),
+        &3000u32.to_be_bytes(),
+    ]
+    .concat();
+
+    let cases = [
+        (
+            "vbr    Xing, MPEG-1 stereo",
+            first_frame(MPEG1_STEREO, 36, &xing(b"Xing", 0xF, 1000)),
+            Some(1000 * 1152),
+        ),
+        (
+            "vbr    Info, MPEG-2 mono",
+            first_frame(MPEG2_MONO, 13, &xing(b"Info", 0x1, 500)),
+            Some(500 * 576),
+        ),
+        (
+            "vbr    Xing without a frame count",
+            first_frame(MPEG1_STEREO, 36, &xing(b"Xing", 0x2, 1000)),
+            None,
+        ),
+        (
+            "vbr    VBRI",
+            first_frame(MPEG1_STEREO, 36, &vbri),
+            Some(3000 * 1152),
+        ),
+        (
+            "vbr    junk before the frame",
+            [
+                vec![0; 100],
+                first_frame(MPEG1_STEREO, 36, &xing(b"Xing", 0x1, 7)),
+            ]
+            .concat(),
+            Some(7 * 1152),
+        ),
+        (
+            "vbr    plain CBR frame",
+            first_frame(MPEG1_STEREO, 36, &[]),
+            None,
+        ),
+        ("vbr    cut short", MPEG1_STEREO.to_vec(), None),
+    ];
+    let mut ok = true;
+    for (name, data, expected) in cases {
+        ok &= report(name, vbr::total_frames(&data) == expected);
+    }
+    ok
+}
+
+/// The mixer core: summing, panning and the master limiter
+fn check_mix() -> bool {
+    const FRAMES: usize = 512;
+    const RATE: u32 = 44100;
+    let mut ok = true;
+    let mut out = vec![0i16; FRAMES * 2];
+
+    // four voices at full scale, alternating so the DC blocker lets them by
+    let loud: Vec<i16> = (0..FRAMES * 2)
+        .map(|i| if i / 2 % 2 == 0 { i16::MAX } else { -i16::MAX })
+        .collect();
+    let raw = MasterSettings {
+        limiter: false,
+        soft_clip: false,
+        ..Default::default()
+    };
+    let mut bus = MixBus::new(FRAMES, RATE);
+    bus.clear();
+    for _ in 0..4 {
+        bus.add(&loud, (1.0, 1.0), (1.0, 1.0));
+    }
+    bus.finish(&mut out, &raw);
+    ok &= report(
+        "mix    four full scale voices saturate, no wrap",
+        out.iter()
+            .zip(&loud)
+            .all(|(&o, &l)| o == if l > 0 { i16::MAX } else { i16::MIN }),
+    );
+
+    let near =
+        |(l, r): (f32, f32), (el, er): (f32, f32)| (l - el).abs() < 1e-6 && (r - er).abs() < 1e-6;
+    ok &= report(
+        "mix    pan -1, 0, +1",
+        near(pan_gains(-1.0), (1.0, 0.0))
+            && near(pan_gains(0.0), (1.0, 1.0))
+            && near(pan_gains(1.0), (0.0, 1.0)),
+    );
+    // the opposite side fades by the cosine law on the way
+    let (l, r) = pan_gains(0.5);
+    ok &= report(
+        "mix    pan 0.5 is -3 dB on the left",
+        (l - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6 && (r - 1.0).abs() < 1e-6,
+    );
+
+    // a sine pushed 6 dB over full scale comes out at the threshold
+    let limited = MasterSettings {
+        limiter: true,
+        threshold_db: -1.0,
+        soft_clip: false,
+        ..Default::default()
+    };
+    let ceiling = (10f32.powf(limited.threshold_db / 20.0) * i16::MAX as f32).round() as i16;
+    let sine: Vec<i16> = (0..FRAMES * 2)
+        .map(|i| ((2.0 * PI * 1000.0 * (i / 2) as f32 / RATE as f32).sin() * 30000.0) as i16)
+        .collect();
+    let mut bus = MixBus::new(FRAMES, RATE);
+    let mut peak = 0i16;
+    for _ in 0..20 {
+        bus.clear();
+        bus.add(&sine, (2.0, 2.0), (2.0, 2.0));
+        bus.finish(&mut out, &limited);
+        peak = peak.max(out.iter().map(|s| s.saturating_abs()).max().unwrap_or(0));
+    }
+    ok &= report(
+        &format!("mix    limiter holds {peak} under the {ceiling} ceiling"),
+        peak <= ceiling && peak >= ceiling - ceiling / 20,
+    );
+
+    // off with a loud tail in the delay line, then back on over silence
+    let mut limiter = Limiter::new(RATE);
+    let mut loud: Vec<f32> = sine.iter().map(|&s| s as f32 * 2.0).collect();
+    limiter.process(&mut loud);
+    limiter.set_enabled(false);
+    limiter.set_enabled(true);
+    let mut silence = vec![0.0f32; FRAMES * 2];
+    limiter.process(&mut silence);
+    ok &= report(
+        "mix    limiter back on doesn't replay old audio",
+        silence.iter().all(|&s| s == 0.0),
+    );
+    ok
+}
+
+/// The player's chain as the mp3 thread runs it: ReplayGain, EQ and the
+/// limiter in f32, saturated to i16 only at the end
+fn check_track_chain() -> bool {
+    const RATE: u32 = 44100;
+    let settings = MasterSettings {
+        soft_clip: false,
+        ..Default::default()
+    };
+    let ceiling = (10f32.powf(settings.threshold_db / 20.0) * i16::MAX as f32).round() as i16;
+
+    // full scale, +6 dB of ReplayGain without a peak tag, +12 dB of EQ
+    let mut graphic = [0i8; MAX_BANDS];
+    graphic[5] = 12;
+    let mut eq = Eq::new(RATE);
+    eq.set(&EqDesign::from_preset(EqPreset::Graphic, &graphic));
+    let mut rg = SmoothGain::new(1.0);
+    let mut master = Master::new(RATE);
+    let input = stereo_tone(&[(1.0, 0.0)], 1000.0, 0.0, RATE);
+    let mut chain = vec![0.0f32; 1152 * 2];
+    let mut out = Vec::new();
+    for chunk in input.chunks_exact(1152 * 2) {
+        for (c, &s) in chain.iter_mut().zip(chunk) {
+            *c = s as f32;
+        }
+        rg.process_f32(2.0, &mut chain, 2);
+        eq.process(&mut chain);
+        master.process(&mut chain, &settings);
+        out.extend(chain.iter().map(|&c| dsp::gain::to_i16(c)));
+    }
+    let peak = out.iter().map(|s| s.saturating_abs()).max().unwrap_or(0);
+    // only the start of the ramp gets through unreduced, then it's held
+    let held = &out[RATE as usize / 5..];
+    let held_peak = held.iter().map(|s| s.saturating_abs()).max().unwrap_or(0);
+    report(
+        &format!("chain  +18 dB of boosts held to {held_peak} under {ceiling}"),
+        peak <= ceiling && held_peak >= ceiling - ceiling / 20,
+    )
+}
+
+/// Biquad responses against what the RBJ cookbook says they should be
+fn check_eq() -> bool {
+    const RATE: u32 = 44100;
+    const TOLERANCE_DB: f32 = 0.05;
+    let nyquist = RATE as f32 / 2.0;
+    let mut ok = true;
+    let mut expect = |name: String, band: Band, at: &[(f32, f32)]| {
+        let f = Biquad::new(&band, RATE);
+        let worst = at
+            .iter()
+            .map(|&(freq, db)| (f.response_db(freq, RATE) - db).abs())
+            .fold(0.0f32, f32::max);
+        ok &= report(&name, worst <= TOLERANCE_DB);
+    };
+
+    for (freq, gain, q) in [
+        (100.0, 6.0, 0.707),
+        (1000.0, -9.0, 1.414),
+        (8000.0, 12.0, 4.0),
+    ] {
+        let band = Band::new(FilterKind::Peaking, freq, gain, q);
+        expect(
+            format!("eq     peaking {freq} Hz {gain:+} dB Q {q}"),
+            band,
+            // the bilinear transform pins DC and Nyquist, two decades off is close
+            &[
+                (freq, gain),
+                (0.0, 0.0),
+                (nyquist, 0.0),
+                (freq / 100.0, 0.0),
+            ],
+        );
+    }
+    for (freq, gain) in [(100.0, 6.0), (250.0, -8.0)] {
+        let band = Band::new(FilterKind::LowShelf, freq, gain, 0.707);
+        expect(
+            format!("eq     low shelf {freq} Hz {gain:+} dB"),
+            band,
+            &[(0.0, gain), (freq, gain / 2.0), (nyquist, 0.0)],
+        );
+    }
+    for (freq, gain) in [(4000.0, 4.0), (10000.0, -6.0)] {
+        let band = Band::new(FilterKind::HighShelf, freq, gain, 0.707);
+        expect(
+            format!("eq     high shelf {freq} Hz {gain:+} dB"),
+            band,
+            &[(0.0, 0.0), (freq, gain / 2.0), (nyquist, gain)],
+        );
+    }
+
+    let flat = EqDesign::from_preset(EqPreset::Flat, &[3; MAX_BANDS]);
+    let graphic = EqDesign::from_preset(EqPreset::Graphic, &[0; MAX_BANDS]);
+    let freqs = (0..=100).map(|i| 20.0 * 1000f32.powf(i as f32 / 100.0));
+    ok &= report(
+        "eq     flat preset and a zeroed graphic are 0 dB",
+        flat.is_flat()
+            && graphic.is_flat()
+            && freqs
+                .clone()
+                .all(|f| flat.response_db(f, RATE) == 0.0 && graphic.response_db(f, RATE) == 0.0),
+    );
+
+    // the filter as it runs matches the response it reports
+    let band = Band::new(FilterKind::Peaking, 1000.0, 6.0, 1.414);
+    let f = Biquad::new(&band, RATE);
+    let mut state = BiquadState::default();
+    let (mut input, mut output) = (0.0f64, 0.0f64);
+    for i in 0..RATE as usize {
+        let x = (2.0 * PI * 1000.0 * i as f32 / RATE as f32).sin();
+        let y = state.process(&f, x);
+        // past the transient
+        if i >= RATE as usize / 10 {
+            input += (x * x) as f64;
+            output += (y * y) as f64;
+        }
+    }
+    let measured = 10.0 * (output / input).log10() as f32;
+    ok &= report(
+        &format!("eq     1 kHz sine through +6 dB peaking reads {measured:+.2} dB"),
+        (measured - 6.0).abs() <= TOLERANCE_DB,
+    );
+
+    // full scale through the boosts comes out whole, the preamp makes room
+    for (name, boosted, hz) in [
+        ("+12 dB at 1 kHz", &[1000.0][..], 1000.0),
+        ("+12 dB at 1 and 2 kHz", &[1000.0, 2000.0][..], 1414.0),
+    ] {
+        let graphic = GRAPHIC_FREQS.map(|f| if boosted.contains(&f) { 12 } else { 0 });
+        let design = EqDesign::from_preset(EqPreset::Graphic, &graphic);
+        let mut eq = Eq::new(RATE);
+        eq.set(&design);
+        let input: Vec<f32> = stereo_tone(&[(1.0, 0.0)], hz as f64, 0.0, RATE)
+            .iter()
+            .map(|&s| s as f32)
+            .collect();
+        let mut output = input.clone();
+        for chunk in output.chunks_mut(1152 * 2) {
+            eq.process(chunk);
+        }
+        let power = |s: &[f32]| s.iter().map(|&x| (x as f64).powi(2)).sum::<f64>();
+        // past the transient
+        let skip = RATE as usize / 5;
+        let measured = 10.0 * (power(&output[skip..]) / power(&input[skip..])).log10() as f32;
+        let expected = design.response_db(hz, RATE) + design.preamp_db(RATE);
+        ok &= report(
+            &format!("eq     full scale, {name}, {measured:+.2} dB"),
+            expected <= 0.01 && (measured - expected).abs() <= TOLERANCE_DB,
+        );
+    }
+    ok
+}
+
+fn check_ab_loop() -> bool {
+    // a low rate keeps the 30 s cap small
+    const RATE: u32 = 100;
+    const BUF: usize = 1000;
+    let mut ok = true;
+    let buf: Vec<i16> = (0..BUF as i16).collect();
+
+    let mut ab = AbLoop::new(RATE);
+    ab.mark(40, 0);
+    ab.mark(40, 0);
+    ok &= report(
+        "ab     B before any audio is ignored",
+        ab.points() == Some((40, None)),
+    );
+    ab.record(&buf);
+    ab.mark(540, 0);
+    let mut out = vec![0i16; BUF + 10];
+    ab.read(&mut out);
+    ok &= report(
+        "ab     B after one buffer, read wraps back to A",
+        ab.points() == Some((40, Some(540))) && out[..BUF] == buf[..] && out[BUF..] == buf[..10],
+    );
+    ab.mark(0, 0);
+    ok &= report("ab     third mark clears", ab.points().is_none());
+
+    ab.mark(0, 0);
+    let mut buffers = 0;
+    while !ab.is_looping() && buffers < 100 {
+        ab.record(&buf);
+        buffers += 1;
+    }
+    let cap = MAX_LOOP_SECS * RATE;
+    ok &= report(
+        "ab     B set by itself at the cap, on a buffer edge",
+        ab.points() == Some((0, Some(cap))) && buffers * BUF == cap as usize * 2,
+    );
+
+    // A reaches back into what was recorded before it, B cuts the capture short
+    let mut ab = AbLoop::new(RATE);
+    ab.record(&buf);
+    ab.mark(500, 20);
+    ab.record(&buf);
+    ab.mark(1000, 30);
+    let mut out = vec![0i16; BUF];
+    ab.read(&mut out);
+    ok &= report(
+        "ab     marks behind the decoder land behind it",
+        ab.points() == Some((480, Some(970)))
+            && out[..40] == buf[960..]
+            && out[40..980] == buf[..940],
+    );
+    ok
+}
+
+/// Speed and pitch changes mid-stream shouldn't drop audio or click. The
+/// biggest step between samples is held against the tone's own
+fn check_stretch() -> bool {
+    const RATE: u32 = 44100;
+    let mut ok = true;
+    let tone = stereo_tone(&[(4.0, -6.0)], 220.0, 0.0, RATE);
+    let steepest = |s: &[i16]| {
+        s.chunks_exact(2)
+            .zip(s.chunks_exact(2).skip(1))
+            .map(|(a, b)| (b[0] as i32 - a[0] as i32).abs())
+            .max()
+            .unwrap_or(0)
+    };
+    let limit = steepest(&tone) * 3 / 2;
+
+    let cases: [(&str, &[(f32, i8)]); 4] = [
+        ("1.25x back to 1x", &[(1.25, 0), (1.0, 0)]),
+        ("1x to 0.8x and back", &[(1.0, 0), (0.8, 0), (1.0, 0)]),
+        ("+1 to +2 st", &[(1.0, 1), (1.0, 2)]),
+        ("+2 st back to 0", &[(1.0, 2), (1.0, 0)]),
+    ];
+    for (name, steps) in cases {
+        let mut stretch = Stretch::new(RATE);
+        let mut out = Vec::new();
+        let pieces = tone.chunks(1152 * 2).collect::<Vec<_>>();
+        let per_step = pieces.len() / steps.len();
+        for (i, piece) in pieces.iter().enumerate() {
+            if i % per_step == 0
+                && let Some(&(speed, semitones)) = steps.get(i / per_step)
+            {
+                stretch.set(speed, semitones);
+            }
+            stretch.process(piece, &mut out);
+        }
+        let step = steepest(&out);
+        ok &= report(
+            &format!("stretch {name}, step {step} of {limit}"),
+            step <= limit,
+        );
+    }
+    ok
+}
+
+/// The same sine on both channels, `phase` in radians, as (seconds, dBFS)
+/// sections back to back
+fn stereo_tone(sections: &[(f32, f32)], hz: f64, phase: f64, sampling_rate: u32) -> Vec<i16> {
+    let mut out = Vec::new();
+    let mut i = 0u64;
+    for &(seconds, level_db) in sections {
+        let amplitude = 10f64.powf(level_db as f64 / 20.0) * i16::MAX as f64;
+        for _ in 0..(seconds * sampling_rate as f32) as usize {
+            // cycles can be kept exact in f64, f32 drifts over a minute
+            let cycles = (hz * i as f64 / sampling_rate as f64).fract();
+            let s = ((2.0 * std::f64::consts::PI * cycles + phase).sin() * amplitude).round();
+            out.extend([s as i16; 2]);
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Largest gap between `filters` run over sines from 20 Hz to 20 kHz and the
+/// BS.1770 48kHz K-weighting, in dB, and where it is
+fn k_weighting_error(filters: &[Biquad; 2], sampling_rate: u32) -> (f64, f64) {
+    // the standard's coefficients, evaluated in f64
+    const STAGES: [([f64; 3], [f64; 3]); 2] = [
+        (
+            [1.53512485958697, -2.69169618940638, 1.19839281085285],
+            [1.0, -1.69065929318241, 0.73248077421585],
+        ),
+        ([1.0, -2.0, 1.0], [1.0, -1.99004745483398, 0.99007225036621]),
+    ];
+    let reference_db = |hz: f64| {
+        let w = 2.0 * std::f64::consts::PI * hz / 48000.0;
+        let mag2 = |c: &[f64; 3]| {
+            let re = c[0] + c[1] * w.cos() + c[2] * (2.0 * w).cos();
+            let im = c[1] * w.sin() + c[2] * (2.0 * w).sin();
+            re * re + im * im
+        };
+        STAGES
+            .iter()
+            .map(|(b, a)| 10.0 * (mag2(b) / mag2(a)).log10())
+            .sum::<f64>()
+    };
+
+    let mut worst = (0.0f64, 0.0f64);
+    // third octaves
+    for i in 0..31 {
+        // even, so the half second measured holds whole cycles
+        let hz = (10.0 * 2f64.powf(i as f64 / 3.0)).round() * 2.0;
+        let mut state = [BiquadState::default(); 2];
+        let (mut power_in, mut power_out) = (0.0f64, 0.0f64);
+        let n = sampling_rate as usize;
+        for t in 0..n {
+            let x = (2.0 * std::f64::consts::PI * hz * t as f64 / sampling_rate as f64).sin();
+            let [shelf, highpass] = &mut state;
+            let y = highpass.process(&filters[1], shelf.process(&filters[0], x as f32));
+            // past the filters settling
+            if t >= n / 2 {
+                power_in += x * x;
+                power_out += y as f64 * y as f64;
+            }
+        }
+        let error = (10.0 * (power_out / power_in).log10() - reference_db(hz)).abs();
+        if error > worst.0 {
+            worst = (error, hz);
+        }
+    }
+    worst
+}
+
+/// The EBU Tech 3341 / 3342 test signals the meter should read right
+fn check_loudness() -> bool {
+    let mut ok = true;
+    for rate in [44100, 48000] {
+        let (error, hz) = k_weighting_error(&k_weighting(rate), rate);
+        ok &= report(
+            &format!("lufs   {rate} K-weighting off by {error:.4} dB at {hz} Hz"),
+            error <= K_WEIGHTING_TOLERANCE_DB,
+        );
+    }
+    for rate in [44100, 48000] {
+        let measure = |sections: &[(f32, f32)], hz: f64, phase: f64| {
+            let mut meter = LoudnessMeter::new(rate);
+            // in output sized pieces, like the player feeds it
+            for chunk in stereo_tone(sections, hz, phase, rate).chunks(1152 * 2) {
+                meter.process(chunk);
+            }
+            meter.readings()
+        };
+
+        let r = measure(&[(20.0, -23.0)], 997.0, 0.0);
+        ok &= report(
+            &format!(
+                "lufs   {rate} 997 Hz at -23 dBFS reads {:.2} LUFS",
+                r.integrated
+            ),
+            (r.integrated + 23.0).abs() <= 0.1,
+        );
+
+        // the quiet parts fall under the relative gate
+        let r = measure(&[(10.0, -36.0), (60.0, -23.0), (10.0, -36.0)], 1000.0, 0.0);
+        ok &= report(
+            &format!(
+                "lufs   {rate} -36/-23/-36 gated reads {:.2} LUFS",
+                r.integrated
+            ),
+            (r.integrated + 23.0).abs() <= 0.1,
+        );
+
+        let r = measure(&[(20.0, -20.0), (20.0, -30.0)], 1000.0, 0.0);
+        ok &= report(
+            &format!("lufs   {rate} -20/-30 range is {:.1} LU", r.range),
+            (r.range - 10.0).abs() <= 1.0,
+        );
+
+        // every sample lands at -3 dB, between the peaks
+        let r = measure(
+            &[(1.0, 0.0)],
+            rate as f64 / 4.0,
+            std::f64::consts::FRAC_PI_4,
+        );
+        ok &= report(
+            &format!(
+                "lufs   {rate} fs/4 sine at 45 degrees reads {:.2} dBTP",
+                r.true_peak
+            ),
+            (-0.4..=0.2).contains(&r.true_peak),
+        );
+    }
+    ok
+}
+
+fn main() -> ExitCode {
+    let mut ok = true;
+    for n in SIZES {
+        let input = signal(n);
+        let (tw_re, tw_im) = twiddles(n);
+        let (ref_re, ref_im) = reference(&input, &tw_re, &tw_im);
+
+        let mut re = vec![0.0; n / 2];
+        let mut im = vec![0.0; n / 2];
+        fft::fft_real(&input, &mut re, &mut im, &tw_re, &tw_im);
+        let err = max_error(&re, &im, &ref_re, &ref_im);
+        let pass = err <= REAL_TOLERANCE;
+        ok &= pass;
+        println!(
+            "real   {n:>5}: max error {err:.2e} of peak {}",
+            if pass { "ok" } else { "FAIL" }
+        );
+    }
+
+    for n in SIZES {
+        let mut float = FloatFft::new(n, Window::Hann);
+        let mut fixed = FixedFft::new(n, Window::Hann);
+        for level in FIXED_LEVELS_DB {
+            let samples = to_i16(&signal(n), level);
+            let mut reference = vec![0.0; n / 2];
+            let mut power = vec![0.0; n / 2];
+            float.power(&samples, &mut reference);
+            fixed.power(&samples, &mut power);
+            let snr = snr_db(&reference, &power);
+            let pass = snr >= FIXED_MIN_SNR_DB;
+            ok &= pass;
+            println!(
+                "fixed  {n:>5} at {level:>4} dBFS: SNR {snr:.1} dB {}",
+                if pass { "ok" } else { "FAIL" }
+            );
+        }
+    }
+
+    let windows = [
+        Window::Rectangular,
+        Window::Hann,
+        Window::Hamming,
+        Window::Blackman,
+        Window::BlackmanHarris,
+        Window::FlatTop,
+        Window::Kaiser(2),
+        Window::Kaiser(9),
+        Window::Kaiser(20),
+    ];
+    let expected = sine_peak_db::<FloatFft>(Window::Rectangular);
+    for window in windows {
+        let float = sine_peak_db::<FloatFft>(window) - expected;
+        let fixed = sine_peak_db::<FixedFft>(window) - expected;
+
+        let samples = to_i16(&signal(WINDOW_SIZE), -20.0);
+        let mut reference = vec![0.0; WINDOW_SIZE / 2];
+        let mut power = vec![0.0; WINDOW_SIZE / 2];
+        FloatFft::new(WINDOW_SIZE, window).power(&samples, &mut reference);
+        FixedFft::new(WINDOW_SIZE, window).power(&samples, &mut power);
+        let snr = snr_db(&reference, &power);
+
+        let pass = float.abs() <= WINDOW_GAIN_TOLERANCE_DB
+            && fixed.abs() <= WINDOW_GAIN_TOLERANCE_DB
+            && snr >= FIXED_MIN_SNR_DB;
+        ok &= pass;
+        println!(
+            "window {:<20} sine peak {float:+.3} dB f32, {fixed:+.3} dB fixed, fixed SNR {snr:.1} dB {}",
+            format!("{window:?}"),
+            if pass { "ok" } else { "FAIL" }
+        );
+    }
+
+    // bar heights are dBFS between the floor and ceiling, whatever the size
+    for n in [512, 8192, 16384] {
+        let settings = AnalyzerSettings {
+            size: n,
+            ..Default::default()
+        };
+        let range = settings.ceiling_db - settings.floor_db;
+        let mut analyzer = Analyzer::<FloatFft>::new(&settings);
+        for level in [0.0, -20.0, -60.0] {
+            let m = analyzer.analyze(&both(&bin_sine(n, level)), 1.0 / 60.0);
+            let top = analyzer.out_log[..m].iter().fold(0.0f32, |a, &b| a.max(b));
+            let reading = settings.floor_db + top * range;
+            let pass = (reading - level).abs() <= SCALE_TOLERANCE_DB;
+            ok &= pass;
+            println!(
+                "scale  {n:>5} at {level:>4} dBFS: reads {reading:.2} dBFS {}",
+                if pass { "ok" } else { "FAIL" }
+            );
+        }
+    }
+
+    // a sine between bins reads its level in its third-octave band with RMS,
+    // the window's leakage counted back in. Smaller sizes spread the band's
+    // edges over too few bins for that to hold
+    for n in [8192, 16384] {
+        let settings = AnalyzerSettings {
+            size: n,
+            layout: BandLayout::ThirdOctave,
+            aggregation: Aggregation::Rms,
+            ..Default::default()
+        };
+        let range = settings.ceiling_db - settings.floor_db;
+        let centers = fft::band_centers(&settings);
+        let band = centers.iter().position(|&c| c == 1000.0).unwrap();
+        let mut analyzer = Analyzer::<FloatFft>::new(&settings);
+        let level = -20.0;
+        let tone = sine(n, 1000.0, level, settings.sampling_rate);
+        analyzer.analyze(&both(&tone), 1.0 / 60.0);
+        let reading = settings.floor_db + analyzer.out_log[band] * range;
+        let pass = (reading - level).abs() <= SCALE_TOLERANCE_DB;
+        ok &= pass;
+        println!(
+            "rms    {n:>5} 1 kHz at {level:>4} dBFS: reads {reading:.2} dBFS {}",
+            if pass { "ok" } else { "FAIL" }
+        );
+
+        // in one channel only it's half as loud after the downmix, and
+        // still in its own band
+        let left: Vec<i16> = tone.iter().flat_map(|&s| [s, 0]).collect();
+        analyzer.analyze(&left, 1.0 / 60.0);
+        let reading = settings.floor_db + analyzer.out_log[band] * range;
+        let pass = (reading - (level - 6.02)).abs() <= SCALE_TOLERANCE_DB;
+        ok &= pass;
+        println!(
+            "rms    {n:>5} 1 kHz left only at {level:>4} dBFS: reads {reading:.2} dBFS {}",
+            if pass { "ok" } else { "FAIL" }
+        );
+    }
+
+    // no layout reaches past Nyquist at the rate the analyzer runs at
+    let rate = AnalyzerSettings::default().sampling_rate;
+    for layout in BandLayout::ALL {
+        for n in [512, 16384] {
+            let top = bands::layout(layout, n, rate)
+                .iter()
+                .fold(0.0f32, |a, b| a.max(b.high));
+            let pass = top > 0.0 && top <= rate as f32 / 2.0;
+            ok &= pass;
+            println!(
+                "bands  {:<10} {n:>5}: top edge {top:.0} Hz {}",
+                layout.label(),
+                if pass { "ok" } else { "FAIL" }
+            );
+        }
+    }
+
+    // the bars move the same whatever the frame rate, with or without gravity
+    for gravity in [0.0, 4.0] {
+        let settings = AnalyzerSettings {
+            size: 1024,
+            attack_ms: 50.0,
+            release_ms: 250.0,
+            gravity,
+            ..Default::default()
+        };
+        // rising, then falling
+        for (on_s, end_s) in [(0.1, 0.1), (0.5, 0.7)] {
+            let (smooth, smear) = smoothed(&settings, 60, on_s, end_s);
+            for fps in SMOOTHING_RATES {
+                let (s, t) = smoothed(&settings, fps, on_s, end_s);
+                let err = s
+                    .iter()
+                    .zip(&smooth)
+                    .chain(t.iter().zip(&smear))
+                    .map(|(a, b)| (a - b).abs())
+                    .fold(0.0f32, f32::max);
+                let pass = err <= SMOOTHING_TOLERANCE;
+                ok &= pass;
+                println!(
+                    "smooth gravity {gravity} at {end_s} s, {fps:>3} fps: off by {err:.1e} {}",
+                    if pass { "ok" } else { "FAIL" }
+                );
+            }
+        }
+    }
+
+    ok &= check_slice_reader();
+    ok &= check_file_stream();
+    ok &= check_replaygain();
+    ok &= check_vbr();
+    ok &= check_mix();
+    ok &= check_track_chain();
+    ok &= check_eq();
+    ok &= check_ab_loop();
+    ok &= check_stretch();
+    ok &= check_loudness();
+
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
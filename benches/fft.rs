@@ -0,0 +1,70 @@
+// host benchmark for the spectrum analyzer, run with
+//   cargo run --release --manifest-path benches/Cargo.toml --bin fft
+// the numbers only compare versions of the code on the same machine,
+// the PSP's FPU is a lot slower than any host
+
+#[path = "../src/bands.rs"]
+#[allow(dead_code)]
+mod bands;
+#[path = "../src/fft.rs"]
+#[allow(dead_code)]
+mod fft;
+#[path = "../src/fft_fixed.rs"]
+#[allow(dead_code)]
+mod fft_fixed;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use fft::{Analyzer, AnalyzerSettings, FftBackend, FloatFft, MAX_FFT_SIZE, MIN_FFT_SIZE};
+use fft_fixed::FixedFft;
+
+const WARMUP: usize = 20;
+const ITERATIONS: usize = 500;
+
+/// `samples` interleaved stereo, like the PCM ring
+fn bench<B: FftBackend>(name: &str, samples: &[i16]) {
+    let mut analyzer = Analyzer::<B>::new(&AnalyzerSettings {
+        size: samples.len() / 2,
+        ..Default::default()
+    });
+    for _ in 0..WARMUP {
+        black_box(analyzer.analyze(black_box(samples), 1.0 / 60.0));
+    }
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(analyzer.analyze(black_box(samples), 1.0 / 60.0));
+    }
+    let per_frame = start.elapsed() / ITERATIONS as u32;
+    println!(
+        "analyze, {:>5} point {:>5} FFT: {:>6.1} us/frame ({} frames)",
+        samples.len() / 2,
+        name,
+        per_frame.as_secs_f64() * 1e6,
+        ITERATIONS
+    );
+}
+
+fn main() {
+    // a couple of tones and some noise, so no bins are trivially empty
+    let mut seed = 0x1234_5678u32;
+    let samples: Vec<i16> = (0..MAX_FFT_SIZE * 2)
+        .map(|i| {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let noise = (seed >> 8) as f32 / (1 << 24) as f32 - 0.5;
+            let t = (i / 2) as f32 / 44100.0;
+            let x = 0.5 * (2.0 * std::f32::consts::PI * 440.0 * t).sin()
+                + 0.25 * (2.0 * std::f32::consts::PI * 5000.0 * t).sin()
+                + 0.1 * noise;
+            (x * i16::MAX as f32) as i16
+        })
+        .collect();
+
+    let mut size = MIN_FFT_SIZE;
+    while size <= MAX_FFT_SIZE {
+        bench::<FloatFft>("f32", &samples[..size * 2]);
+        bench::<FixedFft>("fixed", &samples[..size * 2]);
+        size *= 2;
+    }
+}
@@ -0,0 +1,31 @@
+// `ByteRead`/`ByteSeek` for `std::fs::File`, so the parsers can run on
+// files on the host. Lives here since the psp crate can't sit next to std.
+
+use crate::error::{Error, SCE_ERROR_ERRNO_EINVAL};
+use crate::io::{ByteRead, ByteSeek, SeekFrom};
+use std::io::{Read, Seek};
+
+// map host errno onto the SCE errno range so codes look the same as on hardware
+fn io_error(e: std::io::Error) -> Error {
+    match e.raw_os_error() {
+        Some(errno) => Error::Io((0x8001_0000u32 | (errno as u32 & 0xFFFF)) as i32),
+        None => Error::Io(SCE_ERROR_ERRNO_EINVAL),
+    }
+}
+
+impl ByteRead for std::fs::File {
+    fn read(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+        Read::read(self, out).map_err(io_error)
+    }
+}
+
+impl ByteSeek for std::fs::File {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let pos = match pos {
+            SeekFrom::Start(off) => std::io::SeekFrom::Start(off),
+            SeekFrom::End(off) => std::io::SeekFrom::End(off),
+            SeekFrom::Current(off) => std::io::SeekFrom::Current(off),
+        };
+        Seek::seek(self, pos).map_err(io_error)
+    }
+}
@@ -0,0 +1,220 @@
+// how the analyzer groups FFT bins into display bands: the original 1.06
+// geometric grouping, ISO octave and third-octave bands, and even steps on
+// the mel, Bark or a linear scale
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+/// Bands the mel, Bark and linear layouts are split into
+const SCALE_BANDS: usize = 64;
+/// What the Hz based layouts cover
+const LOW_HZ: f32 = 20.0;
+const HIGH_HZ: f32 = 20000.0;
+/// Step of the original grouping, each band 6% wider than the last
+const CLASSIC_STEP: f32 = 1.06;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BandLayout {
+    /// bins grouped 6% wider each band, starting at bin 1
+    #[default]
+    Classic,
+    /// ISO 266 octave bands, 31.5 Hz to 16 kHz
+    Octave,
+    /// ISO 266 third-octave bands, 25 Hz to 20 kHz
+    ThirdOctave,
+    Mel,
+    Bark,
+    Linear,
+}
+
+impl BandLayout {
+    pub const ALL: [BandLayout; 6] = [
+        Self::Classic,
+        Self::Octave,
+        Self::ThirdOctave,
+        Self::Mel,
+        Self::Bark,
+        Self::Linear,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Classic => "classic",
+            Self::Octave => "octave",
+            Self::ThirdOctave => "1/3 octave",
+            Self::Mel => "mel",
+            Self::Bark => "Bark",
+            Self::Linear => "linear",
+        }
+    }
+}
+
+/// How the bins in a band add up to its level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Aggregation {
+    /// loudest bin
+    #[default]
+    Peak,
+    /// mean power per bin
+    Mean,
+    /// level of everything in the band, as a real-time analyzer reads it
+    Rms,
+}
+
+impl Aggregation {
+    pub const ALL: [Aggregation; 3] = [Self::Peak, Self::Mean, Self::Rms];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Peak => "peak",
+            Self::Mean => "mean",
+            Self::Rms => "RMS",
+        }
+    }
+}
+
+/// One display band, in Hz or in bins depending on where it's used
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub low: f32,
+    pub high: f32,
+    pub center: f32,
+}
+
+impl Band {
+    fn scaled(self, k: f32) -> Self {
+        Self {
+            low: self.low * k,
+            high: self.high * k,
+            center: self.center * k,
+        }
+    }
+}
+
+/// Bands of `kind` in Hz, for a `size` point FFT at `sampling_rate`,
+/// none of them past Nyquist
+pub fn layout(kind: BandLayout, size: usize, sampling_rate: u32) -> Vec<Band> {
+    let bin_hz = sampling_rate as f32 / size as f32;
+    let nyquist = sampling_rate as f32 * 0.5;
+    let high_hz = HIGH_HZ.min(nyquist);
+    let mut bands: Vec<Band> = match kind {
+        BandLayout::Classic => classic(size).map(|b| b.scaled(bin_hz)).collect(),
+        // base-10 centres, 1 kHz times 10^(n/10) for thirds
+        BandLayout::Octave => iso(-5..=4, 3),
+        BandLayout::ThirdOctave => iso(-16..=13, 1),
+        BandLayout::Mel => even(mel, mel_to_hz, high_hz),
+        BandLayout::Bark => even(bark, bark_to_hz, high_hz),
+        BandLayout::Linear => even(|f| f, |f| f, high_hz),
+    };
+    // the top classic and ISO bands reach over it, they're cut short there
+    bands.retain(|b| b.low < nyquist);
+    for b in &mut bands {
+        b.high = b.high.min(nyquist);
+        b.center = b.center.min(nyquist);
+    }
+    bands
+}
+
+/// The original grouping in bins: bins `f..ceil(f * 1.06)` from bin 1 up
+fn classic(size: usize) -> impl Iterator<Item = Band> {
+    let half = (size / 2) as f32;
+    let mut f = 1.0f32;
+    core::iter::from_fn(move || {
+        if f >= half {
+            return None;
+        }
+        let f1 = libm::ceilf(f * CLASSIC_STEP);
+        // bin k covers k - 0.5..k + 0.5
+        let band = Band {
+            low: f - 0.5,
+            high: f1 - 0.5,
+            center: (f + f1 - 1.0) * 0.5,
+        };
+        f = f1;
+        Some(band)
+    })
+}
+
+/// ISO bands `thirds` thirds of an octave wide, centres 1 kHz * 10^(n thirds / 10)
+fn iso(n: core::ops::RangeInclusive<i32>, thirds: i32) -> Vec<Band> {
+    let edge = libm::powf(10.0, thirds as f32 / 20.0);
+    n.map(|n| {
+        let center = 1000.0 * libm::powf(10.0, (n * thirds) as f32 / 10.0);
+        Band {
+            low: center / edge,
+            high: center * edge,
+            center,
+        }
+    })
+    .collect()
+}
+
+/// `SCALE_BANDS` bands evenly spaced on the scale `to` maps Hz onto
+fn even(to: impl Fn(f32) -> f32, from: impl Fn(f32) -> f32, high_hz: f32) -> Vec<Band> {
+    let (low, high) = (to(LOW_HZ), to(high_hz));
+    let at = |i: f32| from(low + (high - low) * i / SCALE_BANDS as f32);
+    (0..SCALE_BANDS)
+        .map(|i| Band {
+            low: at(i as f32),
+            high: at(i as f32 + 1.0),
+            center: at(i as f32 + 0.5),
+        })
+        .collect()
+}
+
+fn mel(hz: f32) -> f32 {
+    2595.0 * libm::log10f(1.0 + hz / 700.0)
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (libm::powf(10.0, mel / 2595.0) - 1.0)
+}
+
+/// Traunmüller's approximation
+fn bark(hz: f32) -> f32 {
+    26.81 * hz / (1960.0 + hz) - 0.53
+}
+
+fn bark_to_hz(bark: f32) -> f32 {
+    1960.0 * (bark + 0.53) / (26.28 - bark)
+}
+
+/// Bands in Hz to bands in bins
+pub fn to_bins(bands: &[Band], size: usize, sampling_rate: u32) -> Vec<Band> {
+    let k = size as f32 / sampling_rate as f32;
+    bands.iter().map(|b| b.scaled(k)).collect()
+}
+
+/// Power of `band` (in bins) from the bin powers. `enbw` is the window's
+/// noise bandwidth in bins, a sine spreads over that many.
+pub fn band_power(power: &[f32], band: &Band, aggregation: Aggregation, enbw: f32) -> f32 {
+    let last = power.len() - 1;
+    if band.high - band.low < 1.0 {
+        // narrower than a bin, read between the two nearest instead of
+        // every such band showing the same bin
+        let c = band.center.clamp(0.0, last as f32);
+        let k = c as usize;
+        let t = c - k as f32;
+        let next = power[(k + 1).min(last)];
+        return power[k] + (next - power[k]) * t;
+    }
+
+    let first = libm::roundf(band.low.max(0.0)) as usize;
+    let end = (libm::roundf(band.high) as usize).min(last);
+    let (mut peak, mut sum, mut width) = (0.0f32, 0.0f32, 0.0f32);
+    for (k, &p) in power.iter().enumerate().take(end + 1).skip(first) {
+        // how much of bin k lies in the band, ignoring rounding slivers
+        let w = band.high.min(k as f32 + 0.5) - band.low.max(k as f32 - 0.5);
+        if w > 1e-3 {
+            peak = peak.max(p);
+            sum += w * p;
+            width += w;
+        }
+    }
+    match aggregation {
+        Aggregation::Peak => peak,
+        Aggregation::Mean if width > 0.0 => sum / width,
+        Aggregation::Mean => 0.0,
+        Aggregation::Rms => sum / enbw,
+    }
+}
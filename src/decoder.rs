@@ -0,0 +1,316 @@
+// one sceMp3 handle with its own buffers, so two tracks can decode at once
+
+use core::alloc::Layout;
+use core::sync::atomic::Ordering;
+use core::{ffi::c_void, ptr};
+
+extern crate alloc;
+use alloc::alloc::{alloc_zeroed, dealloc, handle_alloc_error};
+use alloc::{boxed::Box, vec};
+
+use psp::sys::{
+    self, Mp3Handle, sceMp3CheckStreamDataNeeded, sceMp3Decode, sceMp3GetInfoToAddStreamData,
+    sceMp3Init, sceMp3NotifyAddStreamData, sceMp3ReleaseMp3Handle, sceMp3ReserveMp3Handle,
+    sceMp3SetLoopNum,
+};
+
+use crate::dsp::replaygain::{ReplayGainMode, replaygain_gain};
+use crate::error::{Error, SCE_MP3_ERROR_BAD_SAMPLE_RATE, SCE_MP3_ERROR_END_OF_STREAM};
+use crate::io::{ByteRead, ByteSeek, ByteStream, SeekFrom};
+use crate::stats::PlayerCounters;
+use crate::tags::{self, ReplayGain};
+use crate::vbr;
+
+const MP3_BUF_SIZE: usize = 16 * 1024; // 16KB for MP3 stream data
+const PCM_BUF_SIZE: usize = 16 * (1152 / 2); // PCM output buffer
+
+/// MPEG 1, 2 and 2.5 sampling rates, anything else is a broken header
+const MP3_RATES: [u32; 9] = [8000, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000];
+
+/// Heap buffer with the 64 byte alignment sceMp3 wants.
+/// Allocated directly so it never passes through the (small) thread stack.
+struct AlignedBuf {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl AlignedBuf {
+    fn new(len: usize) -> Self {
+        let layout = Layout::from_size_align(len, 64).unwrap();
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        Self { ptr, layout }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr, self.layout) };
+    }
+}
+
+/// Find the start of the actual MP3 stream by skipping metadata tags (ID3v2, APE)
+/// Returns the byte offset where the MP3 audio data begins
+fn find_stream_start<S: ByteRead + ByteSeek>(stream: &mut S) -> Result<u32, Error> {
+    let mut header = [0u8; 32];
+
+    stream.seek(SeekFrom::Start(0))?;
+
+    let n = stream.read_full(&mut header)?;
+    if n < 10 {
+        return Ok(0);
+    }
+
+    if header[0] == b'I' && header[1] == b'D' && header[2] == b'3' {
+        let size = ((header[6] as u32 & 0x7F) << 21)
+            | ((header[7] as u32 & 0x7F) << 14)
+            | ((header[8] as u32 & 0x7F) << 7)
+            | (header[9] as u32 & 0x7F);
+        return Ok(size + 10);
+    }
+
+    if header[0] == b'A' && header[1] == b'P' && header[2] == b'E' && header[3] == b'T' {
+        let size = (header[12] as u32)
+            | ((header[13] as u32) << 8)
+            | ((header[14] as u32) << 16)
+            | ((header[15] as u32) << 24);
+        return Ok(size + 32);
+    }
+
+    Ok(0)
+}
+
+/// Fill the MP3 stream buffer from the file
+/// Returns true if there's more data, false if we hit the start of file
+fn fill_stream_buffer<S: ByteRead + ByteSeek>(
+    fd: &mut S,
+    handle: Mp3Handle,
+) -> Result<bool, Error> {
+    let mut dst: *mut u8 = ptr::null_mut();
+    let mut to_write: i32 = 0;
+    let mut src_pos: i32 = 0;
+
+    let status =
+        unsafe { sceMp3GetInfoToAddStreamData(handle, &mut dst, &mut to_write, &mut src_pos) };
+    if status < 0 {
+        return Err(Error::Decoder(status));
+    }
+
+    fd.seek(SeekFrom::Start(src_pos as u64))?;
+
+    let buf = unsafe { core::slice::from_raw_parts_mut(dst, to_write as usize) };
+    let read = fd.read_full(buf)?;
+
+    if read == 0 {
+        // EOF reached
+        let _ = unsafe { sceMp3NotifyAddStreamData(handle, 0) };
+        return Ok(false);
+    }
+
+    let status = unsafe { sceMp3NotifyAddStreamData(handle, read as i32) };
+    if status < 0 {
+        return Err(Error::Decoder(status));
+    }
+
+    Ok(src_pos > 0)
+}
+
+/// An MP3 track being decoded to interleaved stereo i16.
+/// `sceMp3InitResource` must have been called before creating one.
+pub struct Decoder {
+    stream: Box<dyn ByteStream + Send>,
+    handle: Mp3Handle,
+    // owned by the handle until it's released in drop
+    _mp3_buf: AlignedBuf,
+    _pcm_buf: AlignedBuf,
+    pub sampling_rate: u32,
+    pub channels: usize,
+    pub replaygain: ReplayGain,
+    /// decoded stereo samples, `pending_pos..pending_len` not handed out yet
+    pending: Box<[i16]>,
+    pending_pos: usize,
+    pending_len: usize,
+    /// from the Xing/VBRI header, or estimated from the bitrate, which is
+    /// only right for CBR files
+    total_frames: Option<u32>,
+    frames_read: u32,
+    over: bool,
+}
+
+impl Decoder {
+    pub fn new(mut stream: Box<dyn ByteStream + Send>) -> Result<Self, Error> {
+        let file_end = stream.size()?;
+
+        // missing or broken tags just mean no ReplayGain
+        let replaygain = tags::read_replaygain(&mut stream).unwrap_or_default();
+
+        let stream_start = find_stream_start(&mut stream)?;
+
+        let mut first = vec![0u8; vbr::SCAN_LEN];
+        stream.seek(SeekFrom::Start(stream_start as u64))?;
+        let n = stream.read_full(&mut first)?;
+        let header_frames = vbr::total_frames(&first[..n]);
+        drop(first);
+
+        let mp3_buf = AlignedBuf::new(MP3_BUF_SIZE);
+        let pcm_buf = AlignedBuf::new(PCM_BUF_SIZE);
+
+        let mut init_arg = sys::SceMp3InitArg {
+            mp3_stream_start: stream_start,
+            unk1: 0,
+            mp3_stream_end: file_end as u32,
+            unk2: 0,
+            mp3_buf: mp3_buf.ptr as *mut c_void,
+            mp3_buf_size: MP3_BUF_SIZE as i32,
+            pcm_buf: pcm_buf.ptr as *mut c_void,
+            pcm_buf_size: PCM_BUF_SIZE as i32,
+        };
+
+        let handle_raw = unsafe { sceMp3ReserveMp3Handle(&mut init_arg) };
+        if handle_raw < 0 {
+            return Err(Error::Decoder(handle_raw));
+        }
+        let handle = Mp3Handle(handle_raw);
+
+        let init = fill_stream_buffer(&mut stream, handle).and_then(|_| {
+            let status = unsafe { sceMp3Init(handle) };
+            if status < 0 {
+                Err(Error::Decoder(status))
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(e) = init {
+            unsafe { sceMp3ReleaseMp3Handle(handle) };
+            return Err(e);
+        }
+
+        let _ = unsafe { sceMp3SetLoopNum(handle, 0) };
+
+        let sampling_rate = unsafe { sys::sceMp3GetSamplingRate(handle) }.max(0) as u32;
+        // the resampler would take 0 as 1Hz and upsample without bound
+        if !MP3_RATES.contains(&sampling_rate) {
+            unsafe { sceMp3ReleaseMp3Handle(handle) };
+            return Err(Error::Decoder(SCE_MP3_ERROR_BAD_SAMPLE_RATE));
+        }
+        let channels = unsafe { sys::sceMp3GetMp3ChannelNum(handle) }.clamp(1, 2) as usize;
+        let max_sample = unsafe { sys::sceMp3GetMaxOutputSample(handle) }.max(0) as usize;
+        let bitrate_kbps = unsafe { sys::sceMp3GetBitRate(handle) };
+
+        let total_frames = header_frames.or_else(|| {
+            (bitrate_kbps > 0).then(|| {
+                let bytes = file_end.saturating_sub(stream_start as u64);
+                (bytes * 8 * sampling_rate as u64 / (bitrate_kbps as u64 * 1000)) as u32
+            })
+        });
+
+        Ok(Self {
+            stream,
+            handle,
+            _mp3_buf: mp3_buf,
+            _pcm_buf: pcm_buf,
+            sampling_rate,
+            channels,
+            replaygain,
+            pending: vec![0i16; max_sample.max(1152) * 2].into_boxed_slice(),
+            pending_pos: 0,
+            pending_len: 0,
+            total_frames,
+            frames_read: 0,
+            over: false,
+        })
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    /// Frames left before the end of the track, if we could estimate the length
+    pub fn remaining_frames(&self) -> Option<u32> {
+        self.total_frames
+            .map(|total| total.saturating_sub(self.frames_read))
+    }
+
+    /// Estimated length of the track in ms
+    pub fn duration_ms(&self) -> Option<u32> {
+        self.total_frames
+            .map(|total| (total as u64 * 1000 / self.sampling_rate.max(1) as u64) as u32)
+    }
+
+    /// Decode the next MP3 frame into `pending`, returns false at the end of the stream
+    fn decode_frame(&mut self, stats: &PlayerCounters) -> Result<bool, Error> {
+        let needed = unsafe { sceMp3CheckStreamDataNeeded(self.handle) };
+        if needed > 0 {
+            stats
+                .fill
+                .time(|| fill_stream_buffer(&mut self.stream, self.handle))?;
+        }
+
+        let mut buf: *mut i16 = ptr::null_mut();
+        let bytes_decoded = stats
+            .decode
+            .time(|| unsafe { sceMp3Decode(self.handle, &mut buf) });
+
+        if bytes_decoded < 0 && bytes_decoded != SCE_MP3_ERROR_END_OF_STREAM {
+            stats.decode_errors.fetch_add(1, Ordering::Relaxed);
+            return Err(Error::Decoder(bytes_decoded));
+        }
+        if bytes_decoded <= 0 || buf.is_null() {
+            return Ok(false);
+        }
+
+        let samples = unsafe { core::slice::from_raw_parts(buf, bytes_decoded as usize / 2) };
+
+        // always hand out stereo
+        let frames = samples.len() / self.channels;
+        let len = (frames * 2).min(self.pending.len());
+        if self.channels == 2 {
+            self.pending[..len].copy_from_slice(&samples[..len]);
+        } else {
+            for (dst, &s) in self.pending[..len].chunks_exact_mut(2).zip(samples.iter()) {
+                dst[0] = s;
+                dst[1] = s;
+            }
+        }
+        self.pending_len = len;
+        self.pending_pos = 0;
+        Ok(true)
+    }
+
+    /// Fill `out` (interleaved stereo) with the next frames, ReplayGain is
+    /// left to the track chain so boosts don't saturate here.
+    /// Returns the number of frames written, fewer than asked for only at the end of the track.
+    pub fn read(&mut self, out: &mut [i16], stats: &PlayerCounters) -> Result<usize, Error> {
+        let mut written = 0usize;
+        while written < out.len() && !self.over {
+            if self.pending_pos >= self.pending_len {
+                if !self.decode_frame(stats)? {
+                    self.over = true;
+                    break;
+                }
+                continue;
+            }
+            let n = (out.len() - written).min(self.pending_len - self.pending_pos);
+            out[written..written + n]
+                .copy_from_slice(&self.pending[self.pending_pos..self.pending_pos + n]);
+            written += n;
+            self.pending_pos += n;
+        }
+        let frames = written / 2;
+        self.frames_read += frames as u32;
+        Ok(frames)
+    }
+
+    /// gain ReplayGain applies to this track for `mode`
+    pub fn replaygain_gain(&self, mode: ReplayGainMode) -> f32 {
+        replaygain_gain(&self.replaygain, mode)
+    }
+}
+
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        unsafe { sceMp3ReleaseMp3Handle(self.handle) };
+    }
+}
@@ -0,0 +1,153 @@
+// A-B repeat: the region is recorded as it plays the first time and looped
+// from memory after that, so the seams land on exact sample positions and
+// the decoder just waits until the loop is cleared.
+// The decoder runs ahead of the speaker, so a mark is moved back by what's
+// still queued after it. The last half second is kept around for A to
+// reach back into.
+
+extern crate alloc;
+use alloc::{collections::VecDeque, vec::Vec};
+
+/// Longest region that can be looped. The whole region sits in RAM while
+/// the loop is set, 30s of 44.1kHz stereo is ~5.3MB, over a fifth of the
+/// 24MB user memory of a PSP-1000.
+/// B gets set by itself when the recording reaches it.
+pub const MAX_LOOP_SECS: u32 = 30;
+/// How far back a mark can reach, past whatever is queued at 2x speed
+const HISTORY_MS: u32 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Off,
+    /// A is set, recording until B
+    Recording {
+        start: u32,
+    },
+    /// playing `capture` over and over, `pos` in samples
+    Looping {
+        start: u32,
+        pos: usize,
+    },
+}
+
+/// Positions are frames of the track at the mixer rate
+pub struct AbLoop {
+    state: State,
+    /// interleaved stereo from A to B
+    capture: Vec<i16>,
+    max_samples: usize,
+    /// the last `HISTORY_MS` recorded, whether a loop is set or not
+    history: VecDeque<i16>,
+    history_samples: usize,
+}
+
+impl AbLoop {
+    pub fn new(sampling_rate: u32) -> Self {
+        Self {
+            state: State::Off,
+            capture: Vec::new(),
+            max_samples: (MAX_LOOP_SECS * sampling_rate * 2) as usize,
+            history: VecDeque::new(),
+            history_samples: (HISTORY_MS * sampling_rate / 1000 * 2) as usize,
+        }
+    }
+
+    /// Set A, then B, then clear the loop again, `frame` being the next frame
+    /// recorded and `behind` how many before it playback is. B at or before
+    /// A is ignored. The decoder is past B already, clearing the loop picks
+    /// up from there.
+    pub fn mark(&mut self, frame: u32, behind: u32) {
+        self.state = match self.state {
+            State::Off => {
+                // only what this track recorded
+                let behind = behind.min(frame).min((self.history.len() / 2) as u32);
+                self.capture.clear();
+                self.capture.extend(
+                    self.history
+                        .range(self.history.len() - behind as usize * 2..),
+                );
+                State::Recording {
+                    start: frame - behind,
+                }
+            }
+            State::Recording { .. } if self.capture.len() <= behind as usize * 2 => return,
+            State::Recording { start } => {
+                self.capture
+                    .truncate(self.capture.len() - behind as usize * 2);
+                State::Looping { start, pos: 0 }
+            }
+            State::Looping { .. } => return self.clear(),
+        };
+    }
+
+    pub fn clear(&mut self) {
+        self.state = State::Off;
+        self.capture = Vec::new();
+    }
+
+    pub fn is_looping(&self) -> bool {
+        matches!(self.state, State::Looping { .. })
+    }
+
+    /// A, and B once it's set
+    pub fn points(&self) -> Option<(u32, Option<u32>)> {
+        let end = |start: u32| start + (self.capture.len() / 2) as u32;
+        match self.state {
+            State::Off => None,
+            State::Recording { start } => Some((start, None)),
+            State::Looping { start, .. } => Some((start, Some(end(start)))),
+        }
+    }
+
+    /// Keep what the track just played, for A to reach back into and while
+    /// between A and B. Sets B when another buffer like this one wouldn't
+    /// fit under `MAX_LOOP_SECS`, gives up on the loop if memory runs out.
+    pub fn record(&mut self, samples: &[i16]) {
+        self.history.extend(samples);
+        let excess = self.history.len().saturating_sub(self.history_samples);
+        self.history.drain(..excess);
+
+        let State::Recording { start } = self.state else {
+            return;
+        };
+        // the first check only trips if the buffers got bigger
+        if self.capture.len() + samples.len() > self.max_samples
+            || self.capture.try_reserve(samples.len()).is_err()
+        {
+            self.clear();
+            return;
+        }
+        self.capture.extend_from_slice(samples);
+        // B right after this buffer, so the decoder stops exactly there
+        if self.capture.len() + samples.len() > self.max_samples {
+            self.state = State::Looping { start, pos: 0 };
+        }
+    }
+
+    /// Fill `out` from the loop, wrapping from B back to A mid-buffer
+    pub fn read(&mut self, out: &mut [i16]) {
+        let State::Looping { pos, .. } = &mut self.state else {
+            return;
+        };
+        let mut written = 0;
+        while written < out.len() {
+            let n = (out.len() - written).min(self.capture.len() - *pos);
+            out[written..written + n].copy_from_slice(&self.capture[*pos..*pos + n]);
+            written += n;
+            *pos = (*pos + n) % self.capture.len();
+        }
+    }
+
+    /// Track frame `behind` frames before the next one to be read,
+    /// `played` being where the track itself is
+    pub fn frame(&self, played: u32, behind: u32) -> u32 {
+        match self.state {
+            State::Looping { start, pos } => {
+                let len = (self.capture.len() / 2) as i64;
+                let at = (pos / 2) as i64 - behind as i64;
+                start + at.rem_euclid(len) as u32
+            }
+            _ => played.saturating_sub(behind),
+        }
+    }
+}
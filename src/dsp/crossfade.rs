@@ -0,0 +1,38 @@
+// equal-power crossfade from the outgoing track into the incoming one
+
+use core::f32::consts::FRAC_PI_2;
+
+pub const MAX_CROSSFADE_SECS: u32 = 10;
+
+pub struct Crossfade {
+    pos: u32,
+    len: u32,
+}
+
+impl Crossfade {
+    pub fn new(len_frames: u32) -> Self {
+        Self {
+            pos: 0,
+            len: len_frames.max(1),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pos >= self.len
+    }
+
+    /// Mix `incoming` into `out` (both interleaved stereo) and advance the fade.
+    /// Past the end of the fade `out` is just `incoming`. Correlated material
+    /// sums to over full scale mid-fade, the track limiter takes care of it.
+    pub fn mix(&mut self, out: &mut [f32], incoming: &[f32]) {
+        for (o, i) in out.chunks_exact_mut(2).zip(incoming.chunks_exact(2)) {
+            // cos/sin keeps the summed power constant for uncorrelated material
+            let t = (self.pos as f32 / self.len as f32).min(1.0) * FRAC_PI_2;
+            let (g_out, g_in) = (libm::cosf(t), libm::sinf(t));
+            for c in 0..2 {
+                o[c] = o[c] * g_out + i[c] * g_in;
+            }
+            self.pos = self.pos.saturating_add(1);
+        }
+    }
+}
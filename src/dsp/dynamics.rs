@@ -0,0 +1,270 @@
+// end of the output chain: DC blocker, look-ahead limiter, soft clipper
+// works on f32 in i16 scale, full scale is 32767. the limiter also runs at
+// the end of each player's track chain, where ReplayGain, EQ and the
+// crossfade can go over full scale before anything is saturated to i16
+
+extern crate alloc;
+use alloc::{collections::VecDeque, vec, vec::Vec};
+
+use core::f32::consts::PI;
+
+use super::gain::{db_to_gain, gain_to_db};
+
+/// i16 full scale
+const FULL_SCALE: f32 = i16::MAX as f32;
+
+/// How far ahead the limiter looks, also the latency it adds
+const LOOKAHEAD_MS: f32 = 5.0;
+
+/// Corner of the DC blocker, well below anything audible
+const DC_CUTOFF_HZ: f32 = 10.0;
+
+/// Where the soft clip curve starts bending, as a fraction of full scale
+const SOFT_CLIP_KNEE: f32 = 0.8;
+
+pub const MIN_THRESHOLD_DB: i8 = -12;
+
+/// Release times the menu steps through
+pub const RELEASE_STEPS_MS: [u32; 6] = [25, 50, 100, 200, 400, 800];
+
+/// Knobs for the master chain, set from the menu
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MasterSettings {
+    pub limiter: bool,
+    /// dBFS the limiter holds peaks to
+    pub threshold_db: f32,
+    pub release_ms: f32,
+    pub soft_clip: bool,
+}
+
+impl Default for MasterSettings {
+    fn default() -> Self {
+        Self {
+            limiter: true,
+            threshold_db: -1.0,
+            release_ms: 100.0,
+            soft_clip: false,
+        }
+    }
+}
+
+/// One-pole high-pass per channel: y[n] = x[n] - x[n-1] + r * y[n-1]
+pub struct DcBlocker {
+    r: f32,
+    x1: [f32; 2],
+    y1: [f32; 2],
+}
+
+impl DcBlocker {
+    pub fn new(sampling_rate: u32) -> Self {
+        Self {
+            r: 1.0 - 2.0 * PI * DC_CUTOFF_HZ / sampling_rate.max(1) as f32,
+            x1: [0.0; 2],
+            y1: [0.0; 2],
+        }
+    }
+
+    pub fn process(&mut self, buf: &mut [f32]) {
+        for frame in buf.chunks_exact_mut(2) {
+            for (c, s) in frame.iter_mut().enumerate() {
+                let y = *s - self.x1[c] + self.r * self.y1[c];
+                self.x1[c] = *s;
+                self.y1[c] = y;
+                *s = y;
+            }
+        }
+    }
+}
+
+/// Peak limiter that sees `LOOKAHEAD_MS` into the future, so the gain is
+/// already down when a peak arrives instead of clipping its first samples.
+///
+/// The gain needed per frame goes through a sliding minimum over the
+/// look-ahead window, then a moving average of the same length. Every value
+/// averaged over when a frame leaves the delay line saw that frame, so the
+/// smoothed gain is never above what it needs.
+pub struct Limiter {
+    sampling_rate: u32,
+    /// look-ahead window in frames
+    window: usize,
+    threshold: f32,
+    release: f32,
+    release_ms: f32,
+    /// input delayed by `window - 1` frames, interleaved
+    delay: Vec<f32>,
+    delay_pos: usize,
+    /// (frame, gain) candidates for the window minimum, increasing gains
+    mins: VecDeque<(u32, f32)>,
+    frame: u32,
+    /// window minimum with release applied
+    hold: f32,
+    /// last `window` hold values and their sum
+    avg: Vec<f32>,
+    avg_sum: f32,
+    avg_pos: usize,
+    enabled: bool,
+}
+
+impl Limiter {
+    pub fn new(sampling_rate: u32) -> Self {
+        let window = ((LOOKAHEAD_MS / 1000.0 * sampling_rate as f32) as usize).max(2);
+        let defaults = MasterSettings::default();
+        let mut limiter = Self {
+            sampling_rate,
+            window,
+            threshold: 0.0,
+            release: 0.0,
+            release_ms: 0.0,
+            delay: vec![0.0; (window - 1) * 2],
+            delay_pos: 0,
+            mins: VecDeque::with_capacity(window + 1),
+            frame: 0,
+            hold: 1.0,
+            avg: vec![1.0; window],
+            avg_sum: window as f32,
+            avg_pos: 0,
+            enabled: true,
+        };
+        limiter.set(defaults.threshold_db, defaults.release_ms);
+        limiter
+    }
+
+    pub fn set(&mut self, threshold_db: f32, release_ms: f32) {
+        self.threshold = FULL_SCALE * db_to_gain(threshold_db.min(0.0));
+        if release_ms != self.release_ms {
+            self.release_ms = release_ms;
+            let frames = release_ms.max(1.0) / 1000.0 * self.sampling_rate as f32;
+            self.release = libm::expf(-1.0 / frames);
+        }
+    }
+
+    /// Switching back on starts from silence and no reduction, the delay
+    /// line would otherwise replay whatever was in it when it went off
+    pub fn set_enabled(&mut self, on: bool) {
+        if on && !self.enabled {
+            self.delay.fill(0.0);
+            self.delay_pos = 0;
+            self.mins.clear();
+            self.hold = 1.0;
+            self.avg.fill(1.0);
+            self.avg_sum = self.window as f32;
+            self.avg_pos = 0;
+        }
+        self.enabled = on;
+    }
+
+    /// Limit interleaved stereo in place (delayed by the look-ahead),
+    /// returns the lowest gain applied
+    pub fn process(&mut self, buf: &mut [f32]) -> f32 {
+        let window = self.window as u32;
+        let mut lowest = 1.0f32;
+        for frame in buf.chunks_exact_mut(2) {
+            let peak = frame[0].abs().max(frame[1].abs());
+            let needed = if peak > self.threshold {
+                self.threshold / peak
+            } else {
+                1.0
+            };
+
+            while self.mins.back().is_some_and(|&(_, g)| g >= needed) {
+                self.mins.pop_back();
+            }
+            self.mins.push_back((self.frame, needed));
+            while self
+                .mins
+                .front()
+                .is_some_and(|&(f, _)| self.frame.wrapping_sub(f) >= window)
+            {
+                self.mins.pop_front();
+            }
+            let min = self.mins.front().map_or(1.0, |&(_, g)| g);
+            self.frame = self.frame.wrapping_add(1);
+
+            self.hold = if min < self.hold {
+                min
+            } else {
+                min + (self.hold - min) * self.release
+            };
+
+            self.avg_sum += self.hold - self.avg[self.avg_pos];
+            self.avg[self.avg_pos] = self.hold;
+            self.avg_pos += 1;
+            if self.avg_pos == self.avg.len() {
+                self.avg_pos = 0;
+                // don't let rounding in the running sum build up
+                self.avg_sum = self.avg.iter().sum();
+            }
+            let gain = (self.avg_sum / self.window as f32).min(1.0);
+            lowest = lowest.min(gain);
+
+            let d = &mut self.delay[self.delay_pos * 2..self.delay_pos * 2 + 2];
+            let (l, r) = (d[0], d[1]);
+            d.copy_from_slice(frame);
+            self.delay_pos = (self.delay_pos + 1) % (self.window - 1);
+
+            frame[0] = l * gain;
+            frame[1] = r * gain;
+        }
+        lowest
+    }
+}
+
+/// Bend samples above the knee smoothly towards full scale instead of
+/// clipping them flat
+pub fn soft_clip(buf: &mut [f32]) {
+    let knee = SOFT_CLIP_KNEE * FULL_SCALE;
+    let room = FULL_SCALE - knee;
+    for s in buf.iter_mut() {
+        let a = s.abs();
+        if a > knee {
+            let bent = knee + room * libm::tanhf((a - knee) / room);
+            *s = bent.copysign(*s);
+        }
+    }
+}
+
+/// Everything that runs on the summed bus right before it goes out
+pub struct Master {
+    dc: DcBlocker,
+    limiter: Limiter,
+    /// 0 or less, how far the limiter pulled the last buffer down
+    reduction_db: f32,
+}
+
+impl Master {
+    pub fn new(sampling_rate: u32) -> Self {
+        Self {
+            dc: DcBlocker::new(sampling_rate),
+            limiter: Limiter::new(sampling_rate),
+            reduction_db: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, buf: &mut [f32], settings: &MasterSettings) {
+        self.dc.process(buf);
+        self.limiter.set_enabled(settings.limiter);
+        self.reduction_db = if settings.limiter {
+            self.limiter.set(settings.threshold_db, settings.release_ms);
+            gain_to_db(self.limiter.process(buf))
+        } else {
+            0.0
+        };
+        if settings.soft_clip {
+            soft_clip(buf);
+        }
+    }
+
+    /// Peak gain reduction over the last buffer, in dB (0 = none)
+    pub fn reduction_db(&self) -> f32 {
+        self.reduction_db
+    }
+
+    /// Frames the limiter's look-ahead holds back
+    pub fn latency_frames(&self) -> usize {
+        if self.limiter.enabled {
+            self.limiter.window - 1
+        } else {
+            0
+        }
+    }
+}
@@ -0,0 +1,348 @@
+// parametric equalizer: a chain of biquads (RBJ audio EQ cookbook)
+// the design side (`EqDesign`) has no state, so the UI can ask it for
+// the response curve while the audio thread runs the filters
+
+use core::f32::consts::PI;
+
+use super::gain::db_to_gain;
+
+/// Most bands a design can have, the graphic EQ uses all of them
+pub const MAX_BANDS: usize = 10;
+
+/// Center frequencies of the graphic EQ, one octave apart
+pub const GRAPHIC_FREQS: [f32; MAX_BANDS] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+
+/// Graphic EQ slider range in dB, either side of 0
+pub const GRAPHIC_RANGE_DB: i8 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+    LowShelf,
+    Peaking,
+    HighShelf,
+    /// `gain_db` is ignored
+    LowPass,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub kind: FilterKind,
+    pub freq: f32,
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+impl Band {
+    pub const fn new(kind: FilterKind, freq: f32, gain_db: f32, q: f32) -> Self {
+        Self {
+            kind,
+            freq,
+            gain_db,
+            q,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EqPreset {
+    #[default]
+    Flat,
+    BassBoost,
+    Vocal,
+    Loudness,
+    /// 10 octave bands set from the menu
+    Graphic,
+}
+
+impl EqPreset {
+    pub const ALL: [EqPreset; 5] = [
+        EqPreset::Flat,
+        EqPreset::BassBoost,
+        EqPreset::Vocal,
+        EqPreset::Loudness,
+        EqPreset::Graphic,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            EqPreset::Flat => "flat",
+            EqPreset::BassBoost => "bass boost",
+            EqPreset::Vocal => "vocal",
+            EqPreset::Loudness => "loudness",
+            EqPreset::Graphic => "graphic",
+        }
+    }
+
+    fn bands(self) -> &'static [Band] {
+        use FilterKind::*;
+        const BASS_BOOST: [Band; 1] = [Band::new(LowShelf, 100.0, 6.0, 0.707)];
+        const VOCAL: [Band; 3] = [
+            Band::new(LowShelf, 120.0, -3.0, 0.707),
+            Band::new(Peaking, 1000.0, 2.0, 0.8),
+            Band::new(Peaking, 3000.0, 4.0, 1.0),
+        ];
+        // rough equal-loudness compensation for quiet listening
+        const LOUDNESS: [Band; 2] = [
+            Band::new(LowShelf, 80.0, 6.0, 0.707),
+            Band::new(HighShelf, 10000.0, 4.0, 0.707),
+        ];
+        match self {
+            EqPreset::Flat | EqPreset::Graphic => &[],
+            EqPreset::BassBoost => &BASS_BOOST,
+            EqPreset::Vocal => &VOCAL,
+            EqPreset::Loudness => &LOUDNESS,
+        }
+    }
+}
+
+/// The bands to run, without any filter state
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqDesign {
+    bands: [Band; MAX_BANDS],
+    count: usize,
+}
+
+impl EqDesign {
+    pub fn flat() -> Self {
+        Self {
+            bands: [Band::new(FilterKind::Peaking, 1000.0, 0.0, 1.0); MAX_BANDS],
+            count: 0,
+        }
+    }
+
+    /// `graphic` (dB per `GRAPHIC_FREQS` band) is only used by `EqPreset::Graphic`
+    pub fn from_preset(preset: EqPreset, graphic: &[i8; MAX_BANDS]) -> Self {
+        let mut design = Self::flat();
+        if preset == EqPreset::Graphic {
+            // Q of sqrt(2) is roughly one octave wide
+            for (&freq, &gain) in GRAPHIC_FREQS.iter().zip(graphic.iter()) {
+                if gain != 0 {
+                    design.push(Band::new(FilterKind::Peaking, freq, gain as f32, 1.414));
+                }
+            }
+        } else {
+            preset.bands().iter().for_each(|&b| design.push(b));
+        }
+        design
+    }
+
+    fn push(&mut self, band: Band) {
+        if self.count < MAX_BANDS {
+            self.bands[self.count] = band;
+            self.count += 1;
+        }
+    }
+
+    pub fn bands(&self) -> &[Band] {
+        &self.bands[..self.count]
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.bands().iter().all(|b| b.gain_db == 0.0)
+    }
+
+    /// Cut in dB that brings the chain's highest point down to 0 dB, so a
+    /// boost on full scale material doesn't clip. Shelves peak at DC or
+    /// Nyquist, peaking bands at their center.
+    pub fn preamp_db(&self, sampling_rate: u32) -> f32 {
+        let edges = [1.0, sampling_rate as f32 * 0.49];
+        let peak = self
+            .bands()
+            .iter()
+            .map(|b| b.freq)
+            .chain(edges)
+            .map(|f| self.response_db(f, sampling_rate))
+            .fold(0.0f32, f32::max);
+        -peak
+    }
+
+    /// Gain of the whole chain at `freq`, in dB
+    pub fn response_db(&self, freq: f32, sampling_rate: u32) -> f32 {
+        self.bands()
+            .iter()
+            .map(|b| Biquad::new(b, sampling_rate).response_db(freq, sampling_rate))
+            .sum()
+    }
+}
+
+/// Normalized coefficients (a0 = 1)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Biquad {
+    pub const IDENTITY: Biquad = Biquad {
+        b0: 1.0,
+        b1: 0.0,
+        b2: 0.0,
+        a1: 0.0,
+        a2: 0.0,
+    };
+
+    /// From raw coefficients, normalized by `a[0]`
+    pub fn from_coefficients(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b0: (b[0] / a[0]) as f32,
+            b1: (b[1] / a[0]) as f32,
+            b2: (b[2] / a[0]) as f32,
+            a1: (a[1] / a[0]) as f32,
+            a2: (a[2] / a[0]) as f32,
+        }
+    }
+
+    pub fn new(band: &Band, sampling_rate: u32) -> Self {
+        let fs = sampling_rate.max(1) as f32;
+        // stay clear of Nyquist, the formulas fall apart there
+        let freq = band.freq.clamp(1.0, fs * 0.49);
+        let a = libm::powf(10.0, band.gain_db / 40.0);
+        let w0 = 2.0 * PI * freq / fs;
+        let (sin, cos) = (libm::sinf(w0), libm::cosf(w0));
+        let alpha = sin / (2.0 * band.q.max(0.01));
+        let sqrt_a_alpha = 2.0 * libm::sqrtf(a) * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match band.kind {
+            FilterKind::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            FilterKind::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha),
+                (a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha,
+            ),
+            FilterKind::LowPass => (
+                (1.0 - cos) * 0.5,
+                1.0 - cos,
+                (1.0 - cos) * 0.5,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            FilterKind::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha),
+                (a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha,
+            ),
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// |H(e^jw)| at `freq`, in dB
+    pub fn response_db(&self, freq: f32, sampling_rate: u32) -> f32 {
+        let w = 2.0 * PI * freq / sampling_rate.max(1) as f32;
+        let (c1, s1) = (libm::cosf(w), libm::sinf(w));
+        let (c2, s2) = (libm::cosf(2.0 * w), libm::sinf(2.0 * w));
+
+        let num_re = self.b0 + self.b1 * c1 + self.b2 * c2;
+        let num_im = -(self.b1 * s1 + self.b2 * s2);
+        let den_re = 1.0 + self.a1 * c1 + self.a2 * c2;
+        let den_im = -(self.a1 * s1 + self.a2 * s2);
+
+        let num = num_re * num_re + num_im * num_im;
+        let den = den_re * den_re + den_im * den_im;
+        10.0 * libm::log10f(num.max(1e-20) / den.max(1e-20))
+    }
+}
+
+/// Transposed direct form II state for one channel
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BiquadState {
+    z1: f32,
+    z2: f32,
+}
+
+impl BiquadState {
+    #[inline]
+    pub fn process(&mut self, f: &Biquad, x: f32) -> f32 {
+        let y = f.b0 * x + self.z1;
+        self.z1 = f.b1 * x - f.a1 * y + self.z2;
+        self.z2 = f.b2 * x - f.a2 * y;
+        y
+    }
+}
+
+/// Runs an `EqDesign` over interleaved stereo in the track chain
+pub struct Eq {
+    filters: [Biquad; MAX_BANDS],
+    state: [[BiquadState; 2]; MAX_BANDS],
+    count: usize,
+    /// linear, ramped to `preamp_target` over a buffer like `SmoothGain`
+    preamp: f32,
+    preamp_target: f32,
+    sampling_rate: u32,
+}
+
+impl Eq {
+    pub fn new(sampling_rate: u32) -> Self {
+        Self {
+            filters: [Biquad::IDENTITY; MAX_BANDS],
+            state: [[BiquadState::default(); 2]; MAX_BANDS],
+            count: 0,
+            preamp: 1.0,
+            preamp_target: 1.0,
+            sampling_rate,
+        }
+    }
+
+    /// Switch to `design`. Filter state is kept so the change doesn't click.
+    pub fn set(&mut self, design: &EqDesign) {
+        let bands = design.bands();
+        for (i, f) in self.filters.iter_mut().enumerate() {
+            *f = bands
+                .get(i)
+                .map_or(Biquad::IDENTITY, |b| Biquad::new(b, self.sampling_rate));
+        }
+        // bands that just came in start from silence
+        for s in self.state[self.count.min(bands.len())..].iter_mut() {
+            *s = [BiquadState::default(); 2];
+        }
+        self.count = bands.len();
+        self.preamp_target = db_to_gain(design.preamp_db(self.sampling_rate));
+    }
+
+    /// Interleaved stereo in place, in i16 scale but not clamped
+    pub fn process(&mut self, samples: &mut [f32]) {
+        let start = self.preamp;
+        self.preamp = self.preamp_target;
+        if self.count == 0 && start == 1.0 && self.preamp == 1.0 {
+            return;
+        }
+        let step = (self.preamp - start) / (samples.len() / 2).max(1) as f32;
+        let filters = &self.filters[..self.count];
+        let state = &mut self.state[..self.count];
+        for (i, frame) in samples.chunks_exact_mut(2).enumerate() {
+            let g = start + step * (i + 1) as f32;
+            for (c, s) in frame.iter_mut().enumerate() {
+                let mut x = *s * g;
+                for (f, st) in filters.iter().zip(state.iter_mut()) {
+                    x = st[c].process(f, x);
+                }
+                *s = x;
+            }
+        }
+    }
+}
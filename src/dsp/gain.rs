@@ -0,0 +1,83 @@
+/// Bottom of the volume curve, anything below is treated as silence
+pub const MIN_DB: f32 = -48.0;
+
+pub fn db_to_gain(db: f32) -> f32 {
+    libm::powf(10.0, db / 20.0)
+}
+
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * libm::log10f(gain.max(1e-9))
+}
+
+/// Map a 0..=1 volume slider position to a linear gain.
+/// Linear in dB so each step sounds like the same change, with 0 as true silence.
+pub fn volume_to_gain(volume: f32) -> f32 {
+    let v = volume.clamp(0.0, 1.0);
+    if v <= 0.0 {
+        return 0.0;
+    }
+    db_to_gain(MIN_DB * (1.0 - v))
+}
+
+/// Gain that ramps linearly across a buffer whenever the target changes,
+/// so volume steps don't produce zipper noise
+pub struct SmoothGain {
+    current: f32,
+}
+
+impl SmoothGain {
+    pub fn new(gain: f32) -> Self {
+        Self { current: gain }
+    }
+
+    /// Scale interleaved samples in place, ramping from the last gain to `target`
+    pub fn process(&mut self, target: f32, samples: &mut [i16], channels: usize) {
+        let channels = channels.max(1);
+        let frames = samples.len() / channels;
+        if frames == 0 {
+            return;
+        }
+
+        let start = self.current;
+        self.current = target;
+        if start == 1.0 && target == 1.0 {
+            return;
+        }
+
+        let step = (target - start) / frames as f32;
+        for (i, frame) in samples.chunks_exact_mut(channels).enumerate() {
+            let g = start + step * (i + 1) as f32;
+            for s in frame.iter_mut() {
+                *s = to_i16(*s as f32 * g);
+            }
+        }
+    }
+
+    /// `process` on f32, nothing is clamped so boosts keep their headroom
+    pub fn process_f32(&mut self, target: f32, samples: &mut [f32], channels: usize) {
+        let channels = channels.max(1);
+        let frames = samples.len() / channels;
+        if frames == 0 {
+            return;
+        }
+
+        let start = self.current;
+        self.current = target;
+        if start == 1.0 && target == 1.0 {
+            return;
+        }
+
+        let step = (target - start) / frames as f32;
+        for (i, frame) in samples.chunks_exact_mut(channels).enumerate() {
+            let g = start + step * (i + 1) as f32;
+            for s in frame.iter_mut() {
+                *s *= g;
+            }
+        }
+    }
+}
+
+/// Round and saturate to i16
+pub fn to_i16(v: f32) -> i16 {
+    libm::roundf(v).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
@@ -0,0 +1,72 @@
+// vocal removal: whatever is panned dead center is identical in both
+// channels, so L - R cancels it. Bass is usually centered too, the
+// band-limited mode puts the low end of L + R back.
+
+use super::eq::{Band, Biquad, BiquadState, FilterKind};
+
+/// Below this the center is kept in `KeepBass` mode
+const BASS_CUTOFF_HZ: f32 = 150.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KaraokeMode {
+    #[default]
+    Off,
+    /// L - R on both channels
+    Full,
+    /// L - R above `BASS_CUTOFF_HZ`, the center below it
+    KeepBass,
+}
+
+impl KaraokeMode {
+    pub const ALL: [KaraokeMode; 3] = [Self::Off, Self::Full, Self::KeepBass];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Full => "on",
+            Self::KeepBass => "keep bass",
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(v: u8) -> Self {
+        Self::ALL.get(v as usize).copied().unwrap_or_default()
+    }
+}
+
+pub struct Karaoke {
+    lowpass: Biquad,
+    /// two in series for a steeper crossover
+    state: [BiquadState; 2],
+}
+
+impl Karaoke {
+    pub fn new(sampling_rate: u32) -> Self {
+        let band = Band::new(FilterKind::LowPass, BASS_CUTOFF_HZ, 0.0, 0.707);
+        Self {
+            lowpass: Biquad::new(&band, sampling_rate),
+            state: [BiquadState::default(); 2],
+        }
+    }
+
+    /// Interleaved stereo in place, the result is mono on both channels
+    pub fn process(&mut self, mode: KaraokeMode, samples: &mut [f32]) {
+        if mode == KaraokeMode::Off {
+            return;
+        }
+        for frame in samples.chunks_exact_mut(2) {
+            let (l, r) = (frame[0], frame[1]);
+            let mut y = (l - r) * 0.5;
+            if mode == KaraokeMode::KeepBass {
+                let mid = (l + r) * 0.5;
+                let lp = self.state[0].process(&self.lowpass, mid);
+                y += self.state[1].process(&self.lowpass, lp);
+            }
+            frame[0] = y;
+            frame[1] = y;
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// per-channel peak and RMS levels in dBFS for the stereo meter
+// the peak follows the chosen ballistics, RMS always integrates over 300ms
+
+use super::gain::gain_to_db;
+
+/// Bottom of the meter scale, quieter reads as this
+pub const METER_FLOOR_DB: f32 = -60.0;
+
+const RMS_MS: f32 = 300.0;
+/// Peak hold stays put this long before falling
+const HOLD_MS: f32 = 1500.0;
+const HOLD_FALL_DB_PER_S: f32 = 20.0;
+/// The clip light stays on this long after the last clipped sample
+const CLIP_MS: f32 = 2000.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Ballistics {
+    /// slow and symmetric, ~300ms to reach a steady tone
+    #[default]
+    Vu,
+    /// fast attack, falls at ~8.6 dB/s (BBC style)
+    Ppm,
+}
+
+impl Ballistics {
+    pub const ALL: [Ballistics; 2] = [Self::Vu, Self::Ppm];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Vu => "VU",
+            Self::Ppm => "PPM",
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(v: u8) -> Self {
+        Self::ALL.get(v as usize).copied().unwrap_or_default()
+    }
+
+    /// (attack, release) time constants in ms
+    fn times_ms(self) -> (f32, f32) {
+        match self {
+            Self::Vu => (65.0, 65.0),
+            // exponential decay with a 1s time constant is 8.7 dB/s
+            Self::Ppm => (2.5, 1000.0),
+        }
+    }
+}
+
+/// What the meter shows for one channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelLevel {
+    pub peak_db: f32,
+    pub rms_db: f32,
+    pub hold_db: f32,
+    pub clip: bool,
+}
+
+impl Default for ChannelLevel {
+    fn default() -> Self {
+        Self {
+            peak_db: METER_FLOOR_DB,
+            rms_db: METER_FLOOR_DB,
+            hold_db: METER_FLOOR_DB,
+            clip: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelState {
+    /// 0..=1 of full scale, after ballistics
+    peak: f32,
+    /// mean square, 0..=1
+    square: f32,
+    hold_db: f32,
+    /// seconds left before the hold starts falling
+    hold_left: f32,
+    clip_left: f32,
+}
+
+pub struct LevelMeter {
+    sampling_rate: f32,
+    rms_coef: f32,
+    channels: [ChannelState; 2],
+}
+
+/// Per-sample smoothing coefficient for a time constant
+fn coef(ms: f32, sampling_rate: f32) -> f32 {
+    1.0 - libm::expf(-1000.0 / (ms * sampling_rate))
+}
+
+impl LevelMeter {
+    pub fn new(sampling_rate: u32) -> Self {
+        let sampling_rate = sampling_rate.max(1) as f32;
+        Self {
+            sampling_rate,
+            rms_coef: coef(RMS_MS, sampling_rate),
+            channels: [ChannelState {
+                hold_db: METER_FLOOR_DB,
+                ..Default::default()
+            }; 2],
+        }
+    }
+
+    /// Run interleaved stereo through the meter
+    pub fn process(&mut self, samples: &[i16], ballistics: Ballistics) {
+        let (attack_ms, release_ms) = ballistics.times_ms();
+        let attack = coef(attack_ms, self.sampling_rate);
+        let release = coef(release_ms, self.sampling_rate);
+        let secs = (samples.len() / 2) as f32 / self.sampling_rate;
+
+        for (c, ch) in self.channels.iter_mut().enumerate() {
+            let mut clipped = false;
+            let mut max = 0.0f32;
+            for &s in samples.iter().skip(c).step_by(2) {
+                let x = s as f32 / i16::MAX as f32;
+                let a = x.abs();
+                max = max.max(a);
+                clipped |= s == i16::MAX || s == i16::MIN;
+                let k = if a > ch.peak { attack } else { release };
+                ch.peak += (a - ch.peak) * k;
+                ch.square += (x * x - ch.square) * self.rms_coef;
+            }
+
+            // the hold shows the sample peak, whatever the ballistics
+            let max_db = level_db(max);
+            if max_db >= ch.hold_db {
+                ch.hold_db = max_db;
+                ch.hold_left = HOLD_MS / 1000.0;
+            } else if ch.hold_left > 0.0 {
+                ch.hold_left -= secs;
+            } else {
+                ch.hold_db = (ch.hold_db - HOLD_FALL_DB_PER_S * secs).max(level_db(ch.peak));
+            }
+
+            ch.clip_left = if clipped {
+                CLIP_MS / 1000.0
+            } else {
+                (ch.clip_left - secs).max(0.0)
+            };
+        }
+    }
+
+    pub fn levels(&self) -> [ChannelLevel; 2] {
+        self.channels.map(|ch| ChannelLevel {
+            peak_db: level_db(ch.peak),
+            rms_db: level_db(libm::sqrtf(ch.square)),
+            hold_db: ch.hold_db,
+            clip: ch.clip_left > 0.0,
+        })
+    }
+}
+
+fn level_db(v: f32) -> f32 {
+    gain_to_db(v).max(METER_FLOOR_DB)
+}
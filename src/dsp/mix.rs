@@ -0,0 +1,74 @@
+// summing voices into one stereo bus
+// accumulates in i32 so stacked voices can't wrap, then runs the master
+// chain (`dsp::dynamics`) and saturates once at the end
+
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+
+use core::f32::consts::FRAC_PI_2;
+
+use super::dynamics::{Master, MasterSettings};
+use super::gain::to_i16;
+
+/// Stereo balance, -1 = hard left, 0 = center (unity), 1 = hard right.
+/// Every voice is stereo, so panning fades the opposite side out instead of
+/// moving a mono signal around.
+pub fn pan_gains(pan: f32) -> (f32, f32) {
+    let pan = pan.clamp(-1.0, 1.0);
+    let fade = |p: f32| libm::cosf(p.max(0.0) * FRAC_PI_2);
+    (fade(pan), fade(-pan))
+}
+
+/// One output buffer worth of interleaved stereo being mixed
+pub struct MixBus {
+    acc: Vec<i32>,
+    /// the mix as f32 on its way through the master chain
+    master_buf: Vec<f32>,
+    master: Master,
+}
+
+impl MixBus {
+    pub fn new(frames: usize, sampling_rate: u32) -> Self {
+        Self {
+            acc: vec![0; frames * 2],
+            master_buf: vec![0.0; frames * 2],
+            master: Master::new(sampling_rate),
+        }
+    }
+
+    /// Start a new buffer
+    pub fn clear(&mut self) {
+        self.acc.fill(0);
+    }
+
+    /// Add interleaved stereo `src`, the (left, right) gains ramp linearly
+    /// from `from` to `to` across the buffer so changes don't click
+    pub fn add(&mut self, src: &[i16], from: (f32, f32), to: (f32, f32)) {
+        let frames = (src.len() / 2).min(self.acc.len() / 2);
+        if frames == 0 {
+            return;
+        }
+        let step_l = (to.0 - from.0) / frames as f32;
+        let step_r = (to.1 - from.1) / frames as f32;
+        let pairs = self.acc.chunks_exact_mut(2).zip(src.chunks_exact(2));
+        for (i, (acc, s)) in pairs.take(frames).enumerate() {
+            let t = (i + 1) as f32;
+            let (gl, gr) = (from.0 + step_l * t, from.1 + step_r * t);
+            acc[0] += libm::roundf(s[0] as f32 * gl) as i32;
+            acc[1] += libm::roundf(s[1] as f32 * gr) as i32;
+        }
+    }
+
+    /// Write the mix to `out` through the master chain, saturating whatever
+    /// is still out of range. Returns the limiter's gain reduction in dB.
+    pub fn finish(&mut self, out: &mut [i16], settings: &MasterSettings) -> f32 {
+        for (m, &a) in self.master_buf.iter_mut().zip(self.acc.iter()) {
+            *m = a as f32;
+        }
+        self.master.process(&mut self.master_buf, settings);
+        for (o, &m) in out.iter_mut().zip(self.master_buf.iter()) {
+            *o = to_i16(m);
+        }
+        self.master.reduction_db()
+    }
+}
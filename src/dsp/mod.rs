@@ -0,0 +1,15 @@
+// PCM processing stages for the output path
+// everything in here is plain math on sample slices, no PSP calls
+
+pub mod ab_loop;
+pub mod crossfade;
+pub mod dynamics;
+pub mod eq;
+pub mod gain;
+pub mod karaoke;
+pub mod loudness;
+pub mod meter;
+pub mod mix;
+pub mod replaygain;
+pub mod resample;
+pub mod stretch;
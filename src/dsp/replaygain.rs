@@ -0,0 +1,53 @@
+use crate::dsp::gain::db_to_gain;
+use crate::tags::ReplayGain;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    /// album values, falling back to track values when a file has none
+    Album,
+}
+
+impl ReplayGainMode {
+    pub const ALL: [ReplayGainMode; 3] = [Self::Off, Self::Track, Self::Album];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Track => "track",
+            Self::Album => "album",
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(v: u8) -> Self {
+        Self::ALL.get(v as usize).copied().unwrap_or_default()
+    }
+}
+
+/// Linear gain to apply for `mode`, 1.0 when the file has no usable tags.
+/// Clipping prevention: never push the tagged peak past full scale.
+pub fn replaygain_gain(rg: &ReplayGain, mode: ReplayGainMode) -> f32 {
+    let (gain_db, peak) = match mode {
+        ReplayGainMode::Off => return 1.0,
+        ReplayGainMode::Track => (rg.track_gain_db, rg.track_peak),
+        // the peak has to come with the gain, an album peak says nothing
+        // about how far the track gain can go
+        ReplayGainMode::Album if rg.album_gain_db.is_some() => (rg.album_gain_db, rg.album_peak),
+        ReplayGainMode::Album => (rg.track_gain_db, rg.track_peak),
+    };
+    let Some(db) = gain_db else {
+        return 1.0;
+    };
+
+    let gain = db_to_gain(db);
+    match peak {
+        Some(p) if p > 0.0 => gain.min(1.0 / p),
+        _ => gain,
+    }
+}
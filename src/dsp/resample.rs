@@ -0,0 +1,181 @@
+// sample rate conversion to the mixer's fixed output rate
+// polyphase windowed-sinc by default, linear interpolation as the cheap option
+
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+
+use core::f32::consts::PI;
+
+use super::gain::to_i16;
+
+/// Filter length in input frames, the output lags by half of it
+const TAPS: usize = 16;
+/// Fractional positions the kernel is tabulated at, the nearest one is used
+const PHASES: usize = 128;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResampleMode {
+    #[default]
+    Sinc,
+    Linear,
+}
+
+impl ResampleMode {
+    pub const ALL: [ResampleMode; 2] = [ResampleMode::Sinc, ResampleMode::Linear];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ResampleMode::Sinc => "sinc",
+            ResampleMode::Linear => "linear",
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(v: u8) -> Self {
+        match v {
+            1 => ResampleMode::Linear,
+            _ => ResampleMode::Sinc,
+        }
+    }
+}
+
+/// Streaming interleaved stereo resampler with an exact rational step
+pub struct Resampler {
+    in_rate: u32,
+    out_rate: u32,
+    pub mode: ResampleMode,
+    /// `PHASES` rows of `TAPS` coefficients
+    table: Vec<f32>,
+    /// input not fully consumed yet, starting with the history the kernel needs
+    buf: Vec<i16>,
+    /// input frame the next output follows, and how far past it in 1/out_rate units
+    pos: usize,
+    frac: u32,
+}
+
+impl Resampler {
+    pub fn new(in_rate: u32, out_rate: u32, mode: ResampleMode) -> Self {
+        let in_rate = in_rate.max(1);
+        let out_rate = out_rate.max(1);
+        let history = TAPS / 2 - 1;
+        Self {
+            in_rate,
+            out_rate,
+            mode,
+            table: sinc_table(in_rate, out_rate),
+            buf: vec![0; history * 2],
+            pos: history,
+            frac: 0,
+        }
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.in_rate == self.out_rate
+    }
+
+    /// Retune to a new input rate, keeping the history so the audio
+    /// carries on without a click. The kernel is rebuilt in place.
+    pub fn set_in_rate(&mut self, in_rate: u32) {
+        let in_rate = in_rate.max(1);
+        if in_rate != self.in_rate {
+            self.in_rate = in_rate;
+            fill_sinc_table(&mut self.table, in_rate, self.out_rate);
+        }
+    }
+
+    /// How many output frames `frames` input frames turn into
+    pub fn output_frames(&self, frames: u32) -> u32 {
+        (frames as u64 * self.out_rate as u64 / self.in_rate as u64) as u32
+    }
+
+    /// Convert `input` (interleaved stereo) and append the result to `out`
+    pub fn process(&mut self, input: &[i16], out: &mut Vec<i16>) {
+        if self.is_passthrough() {
+            out.extend_from_slice(input);
+            return;
+        }
+
+        self.buf.extend_from_slice(input);
+        let frames = self.buf.len() / 2;
+
+        // the kernel reaches TAPS / 2 frames ahead of `pos`
+        while self.pos + TAPS / 2 < frames {
+            let (l, r) = match self.mode {
+                ResampleMode::Linear => self.linear(),
+                ResampleMode::Sinc => self.sinc(),
+            };
+            out.push(to_i16(l));
+            out.push(to_i16(r));
+
+            self.frac += self.in_rate;
+            while self.frac >= self.out_rate {
+                self.frac -= self.out_rate;
+                self.pos += 1;
+            }
+        }
+
+        // keep only the history the next call needs
+        let consumed = self.pos - (TAPS / 2 - 1);
+        self.buf.drain(..consumed * 2);
+        self.pos -= consumed;
+    }
+
+    fn linear(&self) -> (f32, f32) {
+        let t = self.frac as f32 / self.out_rate as f32;
+        let a = &self.buf[self.pos * 2..self.pos * 2 + 4];
+        let l = a[0] as f32 + (a[2] as f32 - a[0] as f32) * t;
+        let r = a[1] as f32 + (a[3] as f32 - a[1] as f32) * t;
+        (l, r)
+    }
+
+    fn sinc(&self) -> (f32, f32) {
+        let phase = (self.frac as u64 * PHASES as u64 / self.out_rate as u64) as usize;
+        let coeffs = &self.table[phase * TAPS..(phase + 1) * TAPS];
+        let first = self.pos + 1 - TAPS / 2;
+        let input = &self.buf[first * 2..(first + TAPS) * 2];
+
+        let (mut l, mut r) = (0.0f32, 0.0f32);
+        for (&c, s) in coeffs.iter().zip(input.chunks_exact(2)) {
+            l += s[0] as f32 * c;
+            r += s[1] as f32 * c;
+        }
+        (l, r)
+    }
+}
+
+/// Blackman-windowed sinc, low-passed below the lower of the two Nyquist frequencies
+fn sinc_table(in_rate: u32, out_rate: u32) -> Vec<f32> {
+    let mut table = vec![0.0f32; PHASES * TAPS];
+    fill_sinc_table(&mut table, in_rate, out_rate);
+    table
+}
+
+fn fill_sinc_table(table: &mut [f32], in_rate: u32, out_rate: u32) {
+    // relative to the input Nyquist, with some room for the transition band
+    let cutoff = (out_rate as f32 / in_rate as f32).min(1.0) * 0.92;
+    let half = (TAPS / 2) as f32;
+
+    for (p, row) in table.chunks_exact_mut(TAPS).enumerate() {
+        let t = p as f32 / PHASES as f32;
+        for (k, c) in row.iter_mut().enumerate() {
+            // distance from the output position to input frame k
+            let d = k as f32 - (half - 1.0) - t;
+            let x = PI * cutoff * d;
+            let sinc = if x.abs() < 1e-6 {
+                1.0
+            } else {
+                libm::sinf(x) / x
+            };
+            let w = 0.42 + 0.5 * libm::cosf(PI * d / half) + 0.08 * libm::cosf(2.0 * PI * d / half);
+            *c = cutoff * sinc * w.max(0.0);
+        }
+        // unity gain at DC for every phase
+        let sum: f32 = row.iter().sum();
+        if sum != 0.0 {
+            row.iter_mut().for_each(|c| *c /= sum);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+// playback speed without the chipmunk effect, and pitch without changing speed
+// WSOLA: overlap-add windows of the input at a different hop than they are
+// written out, nudging each one to where it lines up best with the last
+
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+
+use core::f32::consts::PI;
+
+use super::gain::to_i16;
+use super::resample::{ResampleMode, Resampler};
+
+/// Window length in frames (~23ms at 44.1kHz)
+const WINDOW: usize = 1024;
+/// Output hop, windows overlap by half
+const HOP: usize = WINDOW / 2;
+/// How far a window may move from its nominal position to line up
+const SEARCH: usize = 256;
+/// Only every n-th frame goes into the similarity measure, it's the hot loop
+const CORR_STRIDE: usize = 4;
+/// The search tries every n-th start first, then refines around the best one
+const COARSE_STEP: usize = 8;
+/// Frames a jump between two paths through the stretcher is ramped out over
+const DECLICK: usize = 64;
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 2.0;
+pub const MAX_SEMITONES: i8 = 12;
+
+/// Interleaved stereo time stretcher, output duration is input / `speed`
+pub struct Wsola {
+    speed: f32,
+    window: Vec<f32>,
+    /// input not consumed yet, `base` is the absolute frame of its first one
+    input: Vec<f32>,
+    base: usize,
+    /// absolute frame the next window should nominally start at
+    nominal: f64,
+    /// absolute start of the last window used, None before the first
+    prev: Option<usize>,
+    /// second half of the last window, waiting for the next to overlap it
+    tail: Vec<f32>,
+}
+
+impl Wsola {
+    pub fn new() -> Self {
+        // periodic Hann, overlapping halves sum to exactly one
+        let window = (0..WINDOW)
+            .map(|i| 0.5 - 0.5 * libm::cosf(2.0 * PI * i as f32 / WINDOW as f32))
+            .collect();
+        Self {
+            speed: 1.0,
+            window,
+            input: Vec::with_capacity((WINDOW + 2 * SEARCH + HOP * 2) * 2),
+            base: 0,
+            nominal: 0.0,
+            prev: None,
+            tail: vec![0.0; HOP * 2],
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED / 2.0, MAX_SPEED * 2.0);
+    }
+
+    /// Forget everything buffered
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.base = 0;
+        self.nominal = 0.0;
+        self.prev = None;
+        self.tail.fill(0.0);
+    }
+
+    /// Input frames taken in that haven't come out yet
+    pub fn buffered_frames(&self) -> usize {
+        (self.base + self.input.len() / 2).saturating_sub(self.nominal as usize)
+    }
+
+    /// Append the buffered input as it is, picking up where the last
+    /// window's second half leaves off, then reset. For going back to
+    /// passthrough without dropping audio.
+    pub fn drain(&mut self, out: &mut Vec<i16>) {
+        let from = self
+            .prev
+            .map_or(self.nominal as usize, |p| p + HOP)
+            .max(self.base);
+        out.extend(
+            self.input[(from - self.base) * 2..]
+                .iter()
+                .map(|&s| to_i16(s)),
+        );
+        self.reset();
+    }
+
+    /// Stretch `input` (interleaved stereo) and append what's ready to `out`
+    pub fn process(&mut self, input: &[i16], out: &mut Vec<i16>) {
+        self.input.extend(input.iter().map(|&s| s as f32));
+
+        loop {
+            let end = self.base + self.input.len() / 2;
+            let nominal = self.nominal as usize;
+            let first = nominal.saturating_sub(SEARCH).max(self.base);
+            let last = nominal + SEARCH;
+            // the natural continuation of the last window has to be there too
+            let needed = last.max(self.prev.map_or(0, |p| p + HOP)) + WINDOW;
+            if needed > end {
+                break;
+            }
+
+            let start = match self.prev {
+                Some(prev) => self.best_offset(prev + HOP, first, last),
+                None => nominal,
+            };
+
+            // the first window goes out as it is instead of fading in,
+            // so leaving passthrough doesn't dip
+            let fresh = self.prev.is_none();
+            let frame = &self.input[(start - self.base) * 2..(start - self.base + WINDOW) * 2];
+            for i in 0..HOP {
+                let (w, w2) = (self.window[i], self.window[i + HOP]);
+                for c in 0..2 {
+                    let s = frame[i * 2 + c];
+                    out.push(to_i16(if fresh {
+                        s
+                    } else {
+                        self.tail[i * 2 + c] + s * w
+                    }));
+                    self.tail[i * 2 + c] = frame[(i + HOP) * 2 + c] * w2;
+                }
+            }
+
+            self.prev = Some(start);
+            self.nominal += HOP as f64 * self.speed as f64;
+
+            // keep what the next search and continuation can still reach
+            let keep = (self.nominal as usize)
+                .saturating_sub(SEARCH)
+                .min(start + HOP);
+            if keep > self.base {
+                self.input.drain(..(keep - self.base) * 2);
+                self.base = keep;
+            }
+        }
+    }
+
+    /// Start in `first..=last` whose first half looks most like the frames at `target`
+    fn best_offset(&self, target: usize, first: usize, last: usize) -> usize {
+        let mono = |f: usize| {
+            let i = (f - self.base) * 2;
+            self.input[i] + self.input[i + 1]
+        };
+        let score = |start: usize| {
+            let (mut corr, mut energy) = (0.0f32, 0.0f32);
+            for k in (0..HOP).step_by(CORR_STRIDE) {
+                let x = mono(start + k);
+                corr += x * mono(target + k);
+                energy += x * x;
+            }
+            corr / libm::sqrtf(energy + 1.0)
+        };
+        let pick = |from: usize, to: usize, step: usize, best: (usize, f32)| {
+            (from..=to)
+                .step_by(step)
+                .map(|s| (s, score(s)))
+                .fold(best, |b, c| if c.1 > b.1 { c } else { b })
+        };
+
+        let coarse = pick(
+            first,
+            last,
+            COARSE_STEP,
+            (target.clamp(first, last), f32::MIN),
+        );
+        let from = coarse.0.saturating_sub(COARSE_STEP - 1).max(first);
+        let to = (coarse.0 + COARSE_STEP - 1).min(last);
+        pick(from, to, 1, coarse).0
+    }
+}
+
+/// Speed and pitch for one stream: WSOLA does the time scaling, pitch shift
+/// is a stretch by the pitch ratio followed by resampling back to length
+pub struct Stretch {
+    wsola: Wsola,
+    speed: f32,
+    semitones: i8,
+    /// None when the pitch is unchanged
+    pitch: Option<Resampler>,
+    stretched: Vec<i16>,
+    sampling_rate: u32,
+    /// back at passthrough, WSOLA still holds audio to hand out first
+    draining: bool,
+    /// last frame handed out, and the offset still being ramped out after
+    /// a switch that can't line up sample for sample
+    last: [i16; 2],
+    jump: bool,
+    ramp: [f32; 2],
+    ramp_left: usize,
+}
+
+impl Stretch {
+    pub fn new(sampling_rate: u32) -> Self {
+        Self {
+            wsola: Wsola::new(),
+            speed: 1.0,
+            semitones: 0,
+            pitch: None,
+            stretched: Vec::new(),
+            sampling_rate,
+            draining: false,
+            last: [0; 2],
+            jump: false,
+            ramp: [0.0; 2],
+            ramp_left: 0,
+        }
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.speed == 1.0 && self.semitones == 0
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// `speed` in `MIN_SPEED..=MAX_SPEED`, pitch in semitones
+    pub fn set(&mut self, speed: f32, semitones: i8) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        let semitones = semitones.clamp(-MAX_SEMITONES, MAX_SEMITONES);
+        if speed == self.speed && semitones == self.semitones {
+            return;
+        }
+        let was_passthrough = self.is_passthrough();
+        self.speed = speed;
+        self.semitones = semitones;
+        let ratio = libm::powf(2.0, semitones as f32 / 12.0);
+        self.wsola.set_speed(speed / ratio);
+
+        if self.is_passthrough() {
+            // the pitch resampler stays until the buffered audio went through it
+            self.draining = true;
+            return;
+        }
+        if was_passthrough && !self.draining {
+            self.wsola.reset();
+        }
+        self.draining = false;
+
+        // reading the stretched audio `ratio` times faster raises it by that much
+        let in_rate = libm::roundf(self.sampling_rate as f32 * ratio) as u32;
+        match (semitones, self.pitch.as_mut()) {
+            (0, Some(_)) => {
+                self.pitch = None;
+                self.jump = true;
+            }
+            (0, None) => {}
+            (_, Some(pitch)) => pitch.set_in_rate(in_rate),
+            (_, None) => {
+                self.pitch = Some(Resampler::new(
+                    in_rate,
+                    self.sampling_rate,
+                    ResampleMode::Sinc,
+                ));
+                self.jump = true;
+            }
+        }
+    }
+
+    /// Input frames held back, in source time
+    pub fn buffered_frames(&self) -> usize {
+        if self.is_passthrough() && !self.draining {
+            0
+        } else {
+            self.wsola.buffered_frames()
+        }
+    }
+
+    /// Append `input` at the current speed and pitch to `out`
+    pub fn process(&mut self, input: &[i16], out: &mut Vec<i16>) {
+        if self.is_passthrough() {
+            if self.draining {
+                self.drain(out);
+            }
+            let from = out.len();
+            out.extend_from_slice(input);
+            self.declick(out, from);
+            return;
+        }
+        let from = out.len();
+        match self.pitch.as_mut() {
+            Some(pitch) => {
+                self.stretched.clear();
+                self.wsola.process(input, &mut self.stretched);
+                pitch.process(&self.stretched, out);
+            }
+            None => self.wsola.process(input, out),
+        }
+        self.declick(out, from);
+    }
+
+    /// Hand out what WSOLA still holds on the way back to passthrough
+    fn drain(&mut self, out: &mut Vec<i16>) {
+        let from = out.len();
+        match self.pitch.take() {
+            Some(mut pitch) => {
+                self.stretched.clear();
+                self.wsola.drain(&mut self.stretched);
+                pitch.process(&self.stretched, out);
+                // the resampler's own delay doesn't come out
+                self.declick(out, from);
+                self.jump = true;
+            }
+            None => {
+                self.wsola.drain(out);
+                self.declick(out, from);
+            }
+        }
+        self.draining = false;
+    }
+
+    /// Ramp out the step to what came before when the path through the
+    /// stretcher changed, and remember the last frame for the next one
+    fn declick(&mut self, out: &mut [i16], from: usize) {
+        let new = &mut out[from..];
+        if new.len() < 2 {
+            return;
+        }
+        if self.jump {
+            self.jump = false;
+            self.ramp = [
+                self.last[0] as f32 - new[0] as f32,
+                self.last[1] as f32 - new[1] as f32,
+            ];
+            self.ramp_left = DECLICK;
+        }
+        for frame in new.chunks_exact_mut(2) {
+            if self.ramp_left == 0 {
+                break;
+            }
+            let k = self.ramp_left as f32 / DECLICK as f32;
+            for (s, ramp) in frame.iter_mut().zip(self.ramp) {
+                *s = to_i16(*s as f32 + ramp * k);
+            }
+            self.ramp_left -= 1;
+        }
+        self.last = [new[new.len() - 2], new[new.len() - 1]];
+    }
+
+    /// Push out what's still buffered, for the end of playback
+    pub fn flush(&mut self, out: &mut Vec<i16>) {
+        if self.is_passthrough() {
+            self.process(&[], out);
+        } else {
+            let silence = vec![0i16; (WINDOW + 2 * SEARCH + HOP) * 2];
+            self.process(&silence, out);
+        }
+    }
+}
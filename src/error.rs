@@ -0,0 +1,142 @@
+use core::fmt;
+
+// codes we special-case in the playback path
+pub const SCE_ERROR_MODULE_ALREADY_LOADED: i32 = 0x80111102u32 as i32;
+pub const SCE_MP3_ERROR_END_OF_STREAM: i32 = 0x80671402u32 as i32;
+pub const SCE_MP3_ERROR_BAD_SAMPLE_RATE: i32 = 0x80671302u32 as i32;
+pub const SCE_ERROR_ERRNO_EINVAL: i32 = 0x80010016u32 as i32;
+pub const SCE_ERROR_BUSY: i32 = 0x80000021u32 as i32;
+
+/// Error returned by the player and the asset streams.
+/// Every variant carries the raw (negative) SCE code it was built from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// `sceIo*` failure (open, read, seek)
+    Io(i32),
+    /// `sceUtilityLoadModule` failure
+    Module(i32),
+    /// `sceMp3*` failure
+    Decoder(i32),
+    /// `sceAudio*` channel reserve/output failure
+    Audio(i32),
+    /// `sceKernel*Thread` failure
+    Thread(i32),
+}
+
+impl Error {
+    /// raw SCE error code
+    pub fn code(&self) -> i32 {
+        match *self {
+            Error::Io(c)
+            | Error::Module(c)
+            | Error::Decoder(c)
+            | Error::Audio(c)
+            | Error::Thread(c) => c,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Io(_) => "io",
+            Error::Module(_) => "module",
+            Error::Decoder(_) => "decoder",
+            Error::Audio(_) => "audio",
+            Error::Thread(_) => "thread",
+        }
+    }
+
+    /// human readable name of the code, if it's one we know about
+    pub fn name(&self) -> Option<&'static str> {
+        code_name(self.code())
+    }
+
+    /// pack into (kind, code) so it can travel through atomics
+    pub(crate) fn to_parts(self) -> (u8, i32) {
+        let kind = match self {
+            Error::Io(_) => 0,
+            Error::Module(_) => 1,
+            Error::Decoder(_) => 2,
+            Error::Audio(_) => 3,
+            Error::Thread(_) => 4,
+        };
+        (kind, self.code())
+    }
+
+    pub(crate) fn from_parts(kind: u8, code: i32) -> Self {
+        match kind {
+            0 => Error::Io(code),
+            1 => Error::Module(code),
+            2 => Error::Decoder(code),
+            3 => Error::Audio(code),
+            _ => Error::Thread(code),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} error 0x{:08X}", self.kind(), self.code() as u32)?;
+        if let Some(name) = self.name() {
+            write!(f, " ({})", name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Look up a known SCE error code
+pub fn code_name(code: i32) -> Option<&'static str> {
+    let name = match code as u32 {
+        0x80000021 => "busy",
+        // io / errno
+        0x80010002 => "file not found",
+        0x80010005 => "i/o error",
+        0x80010009 => "bad file descriptor",
+        0x8001000C => "out of memory",
+        0x8001000D => "permission denied",
+        0x80010013 => "no such device",
+        0x80010016 => "invalid argument",
+        0x80010018 => "too many open files",
+        0x8001001C => "no space left on device",
+        0x80020323 => "bad file descriptor",
+        0x80020329 => "async i/o busy",
+        // kernel / threads
+        0x80020001 => "kernel error",
+        0x80020190 => "kernel out of memory",
+        0x80020191 => "illegal thread attributes",
+        0x80020192 => "illegal thread entry",
+        0x80020193 => "illegal thread priority",
+        0x80020194 => "illegal stack size",
+        0x80020197 => "illegal thread id",
+        0x80020198 => "unknown thread id",
+        // modules
+        0x80111102 => "module already loaded",
+        0x80111103 => "module not loaded",
+        // audio
+        0x80260001 => "audio not initialized",
+        0x80260002 => "audio output busy",
+        0x80260003 => "invalid audio channel",
+        0x80260004 => "audio privilege required",
+        0x80260005 => "no free audio channel",
+        0x80260006 => "invalid sample count",
+        0x80260007 => "invalid audio format",
+        0x80260008 => "audio channel not reserved",
+        0x80260009 => "audio channel not outputting",
+        0x8026000A => "invalid output frequency",
+        0x8026000B => "invalid volume",
+        0x80268002 => "audio channel already reserved",
+        // mp3
+        0x80671001 => "invalid mp3 handle",
+        0x80671002 => "bad mp3 buffer address",
+        0x80671003 => "bad mp3 buffer size",
+        0x80671102 => "mp3 handle not reserved",
+        0x80671103 => "mp3 handle not initialized",
+        0x80671201 => "no mp3 resource available",
+        0x80671301 => "mp3 stream too small",
+        0x80671302 => "unsupported sample rate",
+        0x80671402 => "mp3 end of stream",
+        0x80671501 => "bad mp3 reset frame",
+        0x807F00FD => "invalid codec data",
+        _ => return None,
+    };
+    Some(name)
+}
@@ -2,112 +2,447 @@
 
 use core::f32::consts::PI;
 extern crate alloc;
-use alloc::{boxed::Box, vec};
+use alloc::{boxed::Box, vec, vec::Vec};
 use libm;
 
-pub const FFT_SIZE: usize = 1 << 13; // 8192
+use crate::bands::{self, Aggregation, Band, BandLayout};
 
-pub struct Analyzer {
-    pub in_raw: Box<[f32]>,
-    pub in_win: Box<[f32]>,
-    pub out_re: Box<[f32]>,
-    pub out_im: Box<[f32]>,
+/// FFT sizes the analyzer can run at, powers of two in between
+pub const MIN_FFT_SIZE: usize = 1 << 9; // 512
+pub const MAX_FFT_SIZE: usize = 1 << 14; // 16384
+pub const DEFAULT_FFT_SIZE: usize = 1 << 13; // 8192
+
+/// Kaiser β the menu starts at, sidelobes around -70 dB
+pub const DEFAULT_KAISER_BETA: u8 = 9;
+pub const MAX_KAISER_BETA: u8 = 20;
+
+/// Window applied before the FFT, trading main lobe width for sidelobe level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Window {
+    Rectangular,
+    #[default]
+    Hann,
+    Hamming,
+    Blackman,
+    BlackmanHarris,
+    /// flattest top, for reading levels off the bars
+    FlatTop,
+    /// β, higher is lower sidelobes and a wider main lobe
+    Kaiser(u8),
+}
+
+impl Window {
+    pub const ALL: [Window; 7] = [
+        Self::Rectangular,
+        Self::Hann,
+        Self::Hamming,
+        Self::Blackman,
+        Self::BlackmanHarris,
+        Self::FlatTop,
+        Self::Kaiser(DEFAULT_KAISER_BETA),
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Rectangular => "rectangular",
+            Self::Hann => "Hann",
+            Self::Hamming => "Hamming",
+            Self::Blackman => "Blackman",
+            Self::BlackmanHarris => "Blackman-Harris",
+            Self::FlatTop => "flat-top",
+            Self::Kaiser(_) => "Kaiser",
+        }
+    }
+
+    /// Symmetric window of `size` points, 1.0 at the peak
+    pub fn coefficients(self, size: usize) -> Box<[f32]> {
+        // generalized cosine windows, a0 - a1 cos(2πt) + a2 cos(4πt) - ...
+        let cosines: &[f32] = match self {
+            Self::Rectangular => &[1.0],
+            Self::Hann => &[0.5, 0.5],
+            Self::Hamming => &[0.54, 0.46],
+            Self::Blackman => &[0.42, 0.5, 0.08],
+            Self::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            Self::FlatTop => &[0.21557895, 0.41663158, 0.27726316, 0.083578947, 0.006947368],
+            Self::Kaiser(beta) => {
+                let beta = beta as f32;
+                let norm = bessel_i0(beta);
+                return (0..size)
+                    .map(|i| {
+                        let x = 2.0 * i as f32 / (size - 1) as f32 - 1.0;
+                        bessel_i0(beta * libm::sqrtf((1.0 - x * x).max(0.0))) / norm
+                    })
+                    .collect();
+            }
+        };
+        (0..size)
+            .map(|i| {
+                let t = 2.0 * PI * i as f32 / (size - 1) as f32;
+                cosines
+                    .iter()
+                    .enumerate()
+                    .map(|(k, &a)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sign * a * libm::cosf(k as f32 * t)
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// Zeroth order modified Bessel function of the first kind, by its series
+fn bessel_i0(x: f32) -> f32 {
+    let q = x * x / 4.0;
+    let (mut sum, mut term) = (1.0f32, 1.0f32);
+    for k in 1..50 {
+        term *= q / (k * k) as f32;
+        sum += term;
+        if term < sum * 1e-8 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Equivalent noise bandwidth of the window in bins, how many bins' worth
+/// of power a sine spreads over
+pub(crate) fn enbw(window: &[f32]) -> f32 {
+    let sum: f32 = window.iter().sum();
+    let squares: f32 = window.iter().map(|w| w * w).sum();
+    window.len() as f32 * squares / (sum * sum)
+}
+
+/// Mean of the window: a sine's bin comes out this much lower than with no
+/// window, dividing by it keeps the bars the same height whatever the window
+pub(crate) fn coherent_gain(window: &[f32]) -> f32 {
+    window.iter().sum::<f32>() / window.len() as f32
+}
+
+/// Turns a block of samples into the power of each bin
+pub trait FftBackend {
+    fn new(size: usize, window: Window) -> Self;
+
+    /// Windowed FFT of `samples`, |X[k]|² for bins 0..size/2 into `power`,
+    /// with samples scaled so `i16::MAX` is 1.0 and the window's coherent
+    /// gain taken out
+    fn power(&mut self, samples: &[i16], power: &mut [f32]);
+}
+
+/// The backend `Analyzer` uses unless told otherwise
+#[cfg(not(feature = "fixed-fft"))]
+pub type Backend = FloatFft;
+#[cfg(feature = "fixed-fft")]
+pub type Backend = crate::fft_fixed::FixedFft;
+
+/// e^(2πik/N) for k in 0..N/2
+pub(crate) fn twiddles(size: usize) -> (Box<[f32]>, Box<[f32]>) {
+    let angle = |k: usize| 2.0 * PI * k as f32 / size as f32;
+    (
+        (0..size / 2).map(|k| libm::cosf(angle(k))).collect(),
+        (0..size / 2).map(|k| libm::sinf(angle(k))).collect(),
+    )
+}
+
+/// f32 real-input FFT
+#[cfg_attr(feature = "fixed-fft", allow(dead_code))]
+pub struct FloatFft {
+    /// window with the i16 to f32 scaling and coherent gain folded in
+    window: Box<[f32]>,
+    windowed: Box<[f32]>,
+    twiddle_re: Box<[f32]>,
+    twiddle_im: Box<[f32]>,
+    re: Box<[f32]>,
+    im: Box<[f32]>,
+}
+
+impl FftBackend for FloatFft {
+    fn new(size: usize, window: Window) -> Self {
+        let (twiddle_re, twiddle_im) = twiddles(size);
+        let window = window.coefficients(size);
+        let gain = coherent_gain(&window) * i16::MAX as f32;
+        Self {
+            window: window.iter().map(|w| w / gain).collect(),
+            windowed: vec![0.0f32; size].into_boxed_slice(),
+            twiddle_re,
+            twiddle_im,
+            re: vec![0.0f32; size / 2].into_boxed_slice(),
+            im: vec![0.0f32; size / 2].into_boxed_slice(),
+        }
+    }
+
+    fn power(&mut self, samples: &[i16], power: &mut [f32]) {
+        for ((out, &s), &w) in self
+            .windowed
+            .iter_mut()
+            .zip(samples)
+            .zip(self.window.iter())
+        {
+            *out = s as f32 * w;
+        }
+        fft_real(
+            &self.windowed,
+            &mut self.re,
+            &mut self.im,
+            &self.twiddle_re,
+            &self.twiddle_im,
+        );
+        for ((p, &re), &im) in power.iter_mut().zip(self.re.iter()).zip(self.im.iter()) {
+            *p = re * re + im * im;
+        }
+    }
+}
+
+/// Bounds of the dB scale settings
+pub const MIN_FLOOR_DB: i8 = -120;
+pub const MAX_CEILING_DB: i8 = 0;
+/// The scale is never squeezed narrower than this
+pub const MIN_RANGE_DB: i8 = 24;
+
+/// AGC follows a louder passage in about a second and a quieter one in ten
+const AGC_ATTACK_S: f32 = 1.0;
+const AGC_RELEASE_S: f32 = 10.0;
+/// Most the AGC will lift or drop the bars by
+const MAX_AGC_DB: f32 = 40.0;
+
+/// Bar attack and release choices, 0 follows instantly
+pub const SMOOTHING_STEPS_MS: [u32; 9] = [0, 10, 25, 50, 125, 250, 500, 1000, 2000];
+/// Gravity choices in bar heights per second², 0 is off
+pub const GRAVITY_STEPS: [u32; 6] = [0, 1, 2, 4, 8, 16];
+/// Trails follow the bars this slowly
+const SMEAR_MS: f32 = 333.0;
+
+/// What the analyzer runs with, from the settings menu
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnalyzerSettings {
+    /// points, a power of two in `MIN_FFT_SIZE..=MAX_FFT_SIZE`
+    pub size: usize,
+    pub window: Window,
+    /// dBFS at the bottom and top of the bars, a full scale sine is 0
+    pub floor_db: f32,
+    pub ceiling_db: f32,
+    /// slowly shift the scale so the loudest band sits near the ceiling
+    pub agc: bool,
+    pub layout: BandLayout,
+    pub aggregation: Aggregation,
+    /// of the mono signal the analyzer transforms, once L and R are summed
+    pub sampling_rate: u32,
+    /// time constants of the bars going up and coming down
+    pub attack_ms: f32,
+    pub release_ms: f32,
+    /// bars fall like dropped, in heights per second², instead of easing
+    /// down with `release_ms`. 0 is off
+    pub gravity: f32,
+}
+
+impl Default for AnalyzerSettings {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_FFT_SIZE,
+            window: Window::default(),
+            floor_db: -90.0,
+            ceiling_db: 0.0,
+            agc: false,
+            layout: BandLayout::default(),
+            aggregation: Aggregation::default(),
+            // the PCM ring's 44.1kHz, downmixed to mono
+            sampling_rate: 44100,
+            // the original look, 8/s both ways
+            attack_ms: 125.0,
+            release_ms: 125.0,
+            gravity: 0.0,
+        }
+    }
+}
+
+pub struct Analyzer<B: FftBackend = Backend> {
+    settings: AnalyzerSettings,
+    backend: B,
+    /// (L+R)/2 of the last frame's samples
+    mono: Box<[i16]>,
+    /// |X[k]|² of bins 0..N/2
+    pub power: Box<[f32]>,
+    /// band heights, 0..=1 from floor to ceiling
     pub out_log: Box<[f32]>,
     pub out_smooth: Box<[f32]>,
     pub out_smear: Box<[f32]>,
+    /// how fast each bar is falling under gravity, heights per second
+    fall: Box<[f32]>,
+    /// dB the AGC is adding to every band
+    agc_db: f32,
+    /// display bands in bins
+    bands: Vec<Band>,
+    /// of the window, for `Aggregation::Rms`
+    enbw: f32,
 }
 
-impl Analyzer {
-    pub fn new() -> Self {
-        let zeros = vec![0.0f32; FFT_SIZE].into_boxed_slice();
+impl<B: FftBackend> Analyzer<B> {
+    pub fn new(settings: &AnalyzerSettings) -> Self {
+        let size = settings
+            .size
+            .clamp(MIN_FFT_SIZE, MAX_FFT_SIZE)
+            .next_power_of_two();
+        let bands = bands::layout(settings.layout, size, settings.sampling_rate);
         Self {
-            in_raw: zeros.clone(),
-            in_win: zeros.clone(),
-            out_re: zeros.clone(),
-            out_im: zeros.clone(),
-            out_log: vec![0.0f32; FFT_SIZE / 2].into_boxed_slice(),
-            out_smooth: vec![0.0f32; FFT_SIZE / 2].into_boxed_slice(),
-            out_smear: vec![0.0f32; FFT_SIZE / 2].into_boxed_slice(),
+            settings: AnalyzerSettings { size, ..*settings },
+            backend: B::new(size, settings.window),
+            bands: bands::to_bins(&bands, size, settings.sampling_rate),
+            enbw: enbw(&settings.window.coefficients(size)),
+            mono: vec![0i16; size].into_boxed_slice(),
+            power: vec![0.0f32; size / 2].into_boxed_slice(),
+            out_log: vec![0.0f32; size / 2].into_boxed_slice(),
+            out_smooth: vec![0.0f32; size / 2].into_boxed_slice(),
+            out_smear: vec![0.0f32; size / 2].into_boxed_slice(),
+            fall: vec![0.0f32; size / 2].into_boxed_slice(),
+            agc_db: 0.0,
         }
     }
 
-    pub fn analyze(&mut self, samples: &[f32], dt: f32) -> usize {
-        assert!(samples.len() == FFT_SIZE);
-
-        // copy raw
-        for i in 0..FFT_SIZE {
-            self.in_raw[i] = samples[i];
-        }
-
-        // Apply Hann window
-        for i in 0..FFT_SIZE {
-            let t = (i as f32) / ((FFT_SIZE - 1) as f32);
-            let hann = 0.5 - 0.5 * libm::cosf(2.0 * PI * t);
-            self.in_win[i] = self.in_raw[i] * hann;
-        }
-
-        // prepare real/imag arrays
-        for i in 0..FFT_SIZE {
-            self.out_re[i] = self.in_win[i];
-            self.out_im[i] = 0.0;
-        }
-
-        fft_inplace(&mut self.out_re, &mut self.out_im);
-
-        // logarithmic squash
-        let step: f32 = 1.06;
-        let mut m: usize = 0;
-        let mut max_amp: f32 = 1.0;
-        let lowf: f32 = 1.0;
-        let half = FFT_SIZE / 2;
-        let mut f: f32 = lowf;
-        while (f as usize) < half {
-            let f1 = libm::ceilf(f * step);
-            let mut a: f32 = 0.0;
-            let start = f as usize;
-            let end = f1 as usize;
-            for q in start..end.min(half) {
-                let val = amp(self.out_re[q], self.out_im[q]);
-                if val > a {
-                    a = val;
-                }
-            }
-            if a > max_amp {
-                max_amp = a;
-            }
-            if m < self.out_log.len() {
-                self.out_log[m] = a;
-            }
-            m += 1;
-            f = f1;
+    pub fn size(&self) -> usize {
+        self.settings.size
+    }
+
+    /// Only between frames. A new size reallocates and starts everything
+    /// (smoothing included) over, a new window rebuilds the backend.
+    pub fn configure(&mut self, settings: &AnalyzerSettings) {
+        let size = self.settings.size;
+        if settings.size != size {
+            *self = Self::new(settings);
+            return;
+        }
+        if settings.window != self.settings.window {
+            self.backend = B::new(size, settings.window);
+            self.enbw = enbw(&settings.window.coefficients(size));
         }
+        if settings.layout != self.settings.layout
+            || settings.sampling_rate != self.settings.sampling_rate
+        {
+            let bands = bands::layout(settings.layout, size, settings.sampling_rate);
+            self.bands = bands::to_bins(&bands, size, settings.sampling_rate);
+        }
+        if !settings.agc {
+            self.agc_db = 0.0;
+        }
+        self.settings = *settings;
+    }
 
-        // normalize
-        if max_amp > 0.0 {
-            for i in 0..m {
-                self.out_log[i] /= max_amp;
-            }
+    /// `samples` are the newest `size()` frames of the PCM ring, interleaved
+    /// stereo, `dt` the seconds since the last call
+    pub fn analyze(&mut self, samples: &[i16], dt: f32) -> usize {
+        let size = self.settings.size;
+        assert!(samples.len() == size * 2);
+
+        // one spectrum for both channels, at the real sampling rate
+        for (m, frame) in self.mono.iter_mut().zip(samples.chunks_exact(2)) {
+            *m = ((frame[0] as i32 + frame[1] as i32) / 2) as i16;
         }
+        self.backend.power(&self.mono, &mut self.power);
 
-        // smoothing and smear
+        // a full scale sine peaks at (N/2)², now that the window's gain is out
+        let full_scale = 4.0 / (size as f32 * size as f32);
+
+        // bands in dBFS for now
+        let m = self.bands.len().min(self.out_log.len());
+        let mut max_db = f32::NEG_INFINITY;
+        for (out, band) in self.out_log.iter_mut().zip(&self.bands) {
+            let p = bands::band_power(&self.power, band, self.settings.aggregation, self.enbw);
+            let db = power_db(p * full_scale);
+            max_db = max_db.max(db);
+            *out = db;
+        }
+
+        let AnalyzerSettings {
+            floor_db,
+            ceiling_db,
+            ..
+        } = self.settings;
+        // silence isn't something to turn up
+        if self.settings.agc && max_db > floor_db {
+            let target = (ceiling_db - max_db).clamp(-MAX_AGC_DB, MAX_AGC_DB);
+            // the gain coming down is the music getting louder
+            let time = if target < self.agc_db {
+                AGC_ATTACK_S
+            } else {
+                AGC_RELEASE_S
+            };
+            self.agc_db += (target - self.agc_db) * (dt / time).min(1.0);
+        }
+
+        // dB to bar height
+        let range = (ceiling_db - floor_db).max(MIN_RANGE_DB as f32);
         for i in 0..m {
-            let smoothness = 8.0f32;
-            let smearness = 3.0f32;
-            self.out_smooth[i] += (self.out_log[i] - self.out_smooth[i]) * smoothness * dt;
-            self.out_smear[i] += (self.out_smooth[i] - self.out_smear[i]) * smearness * dt;
+            let db = self.out_log[i] + self.agc_db;
+            self.out_log[i] = ((db - floor_db) / range).clamp(0.0, 1.0);
+        }
+
+        // smoothing and smear, in real time whatever the frame rate
+        let attack = follow(self.settings.attack_ms, dt);
+        let release = follow(self.settings.release_ms, dt);
+        let smear = follow(SMEAR_MS, dt);
+        // the smear follows a moving bar, take it as moving in a straight
+        // line over the frame so that comes out the same at any frame rate
+        let ramp = if dt > 0.0 {
+            1.0 - smear * SMEAR_MS / (dt * 1000.0)
+        } else {
+            0.0
+        };
+        let gravity = self.settings.gravity;
+        for i in 0..m {
+            let (target, bar) = (self.out_log[i], self.out_smooth[i]);
+            let trail = self.out_smear[i];
+            self.out_smooth[i] = if target >= bar {
+                self.fall[i] = 0.0;
+                bar + (target - bar) * attack
+            } else if gravity > 0.0 {
+                // exact for any dt, so frame rate doesn't change the fall
+                let v = self.fall[i];
+                let next = bar - v * dt - 0.5 * gravity * dt * dt;
+                if next > target {
+                    self.fall[i] = v + gravity * dt;
+                    next
+                } else {
+                    // landed
+                    self.fall[i] = 0.0;
+                    target
+                }
+            } else {
+                bar + (target - bar) * release
+            };
+            let moved = self.out_smooth[i] - bar;
+            self.out_smear[i] = trail + (bar - trail) * smear + moved * ramp;
         }
 
         m
     }
 }
 
-fn amp(re: f32, im: f32) -> f32 {
-    let a = re;
-    let b = im;
-    libm::logf(a * a + b * b)
+/// How far a follower with time constant `ms` closes the gap in `dt` seconds
+fn follow(ms: f32, dt: f32) -> f32 {
+    if ms <= 0.0 {
+        1.0
+    } else {
+        // expm1 keeps the precision when dt is tiny next to ms
+        -libm::expm1f(-dt * 1000.0 / ms)
+    }
 }
 
-fn fft_inplace(re: &mut [f32], im: &mut [f32]) {
+/// Center frequencies in Hz of the bands `analyze` shows with `settings`
+pub fn band_centers(settings: &AnalyzerSettings) -> Vec<f32> {
+    bands::layout(settings.layout, settings.size, settings.sampling_rate)
+        .iter()
+        .map(|b| b.center)
+        .collect()
+}
+
+/// Power relative to full scale in dB, silence is very low rather than -inf
+fn power_db(power: f32) -> f32 {
+    10.0 * libm::log10f(power.max(1e-20))
+}
+
+/// Radix-2 FFT, `tw_re`/`tw_im` hold the first half of the twiddles for
+/// `re.len()` or any larger power of two
+#[cfg_attr(feature = "fixed-fft", allow(dead_code))]
+pub(crate) fn fft_inplace(re: &mut [f32], im: &mut [f32], tw_re: &[f32], tw_im: &[f32]) {
     let n = re.len();
     // bit reversal
     let mut j = 0usize;
@@ -126,15 +461,14 @@ fn fft_inplace(re: &mut [f32], im: &mut [f32]) {
 
     let mut len = 2usize;
     while len <= n {
-        let ang = 2.0 * PI / (len as f32);
-        let wlen_re = libm::cosf(ang);
-        let wlen_im = libm::sinf(ang);
         let half = len / 2;
+        // stage `len` reads the table at the stride that makes it len long
+        let stride = tw_re.len() * 2 / len;
         let mut i = 0usize;
         while i < n {
-            let mut w_re = 1.0f32;
-            let mut w_im = 0.0f32;
             for j in 0..half {
+                let w_re = tw_re[j * stride];
+                let w_im = tw_im[j * stride];
                 let u_re = re[i + j];
                 let u_im = im[i + j];
                 let v_re = re[i + j + half] * w_re - im[i + j + half] * w_im;
@@ -143,13 +477,50 @@ fn fft_inplace(re: &mut [f32], im: &mut [f32]) {
                 im[i + j] = u_im + v_im;
                 re[i + j + half] = u_re - v_re;
                 im[i + j + half] = u_im - v_im;
-                // w *= wlen
-                let tmp = w_re * wlen_re - w_im * wlen_im;
-                w_im = w_re * wlen_im + w_im * wlen_re;
-                w_re = tmp;
             }
             i += len;
         }
         len <<= 1;
     }
 }
+
+/// Bins 0..N/2 of the FFT of real `input` (N long) into `re`/`im` (N/2 long),
+/// with the twiddles for N. The even and odd samples go in as one N/2 point
+/// complex FFT and are split apart afterwards, about half the work of a full one.
+#[cfg_attr(feature = "fixed-fft", allow(dead_code))]
+pub(crate) fn fft_real(
+    input: &[f32],
+    re: &mut [f32],
+    im: &mut [f32],
+    tw_re: &[f32],
+    tw_im: &[f32],
+) {
+    let m = input.len() / 2;
+    for k in 0..m {
+        re[k] = input[2 * k];
+        im[k] = input[2 * k + 1];
+    }
+    fft_inplace(re, im, tw_re, tw_im);
+
+    // with Z = E + iO, E and O the spectra of the even and odd samples,
+    // X[k] = E[k] + w^k O[k] and X[m - k] = conj(E[k] - w^k O[k])
+    let (z_re, z_im) = (re[0], im[0]);
+    re[0] = z_re + z_im;
+    im[0] = 0.0;
+    for k in 1..=m / 2 {
+        let (a_re, a_im) = (re[k], im[k]);
+        let (b_re, b_im) = (re[m - k], im[m - k]);
+        // E = (Z[k] + conj Z[m - k]) / 2, O = (Z[k] - conj Z[m - k]) / 2i
+        let e_re = (a_re + b_re) * 0.5;
+        let e_im = (a_im - b_im) * 0.5;
+        let o_re = (a_im + b_im) * 0.5;
+        let o_im = (b_re - a_re) * 0.5;
+        let (w_re, w_im) = (tw_re[k], tw_im[k]);
+        let t_re = o_re * w_re - o_im * w_im;
+        let t_im = o_re * w_im + o_im * w_re;
+        re[k] = e_re + t_re;
+        im[k] = e_im + t_im;
+        re[m - k] = e_re - t_re;
+        im[m - k] = t_im - e_im;
+    }
+}
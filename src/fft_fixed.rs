@@ -0,0 +1,166 @@
+// integer FFT for the analyzer, the Allegrex FPU is slow and the decoder
+// wants it too. Samples and window are Q15, the data is Q31 with a block
+// exponent: before a pass that could overflow, everything is shifted down
+// and the shift is counted, so quiet input keeps all its bits.
+
+extern crate alloc;
+use alloc::{boxed::Box, vec};
+
+use crate::fft::{FftBackend, Window, coherent_gain, twiddles};
+
+/// Twiddles are Q30 so 1.0 is exact
+const TWIDDLE_BITS: u32 = 30;
+/// A radix-2 pass grows a component by up to 1 + √2, keep two bits free
+const HEADROOM_LIMIT: u32 = 1 << 29;
+
+/// Q31 real-input FFT with block floating point
+pub struct FixedFft {
+    /// window in Q15, its coherent gain is taken out when converting back
+    window: Box<[i16]>,
+    /// float backend scale of a Q30 value, before the block exponent
+    scale: f32,
+    twiddle_re: Box<[i32]>,
+    twiddle_im: Box<[i32]>,
+    re: Box<[i32]>,
+    im: Box<[i32]>,
+}
+
+/// `a * b` for b in Q30
+fn mul(a: i32, b: i32) -> i64 {
+    a as i64 * b as i64
+}
+
+fn round_q30(x: i64) -> i32 {
+    ((x + (1 << (TWIDDLE_BITS - 1))) >> TWIDDLE_BITS) as i32
+}
+
+/// OR of the magnitudes, only the highest set bit matters
+fn magnitude_bits(re: &[i32], im: &[i32]) -> u32 {
+    re.iter()
+        .chain(im)
+        .fold(0u32, |bits, &x| bits | x.unsigned_abs())
+}
+
+/// Shift everything down until it's under `HEADROOM_LIMIT`, returns the shift
+fn normalize(re: &mut [i32], im: &mut [i32], bits: u32) -> u32 {
+    let mut shift = 0;
+    while bits >> shift >= HEADROOM_LIMIT {
+        shift += 1;
+    }
+    if shift > 0 {
+        for x in re.iter_mut().chain(im.iter_mut()) {
+            *x >>= shift;
+        }
+    }
+    shift
+}
+
+/// Radix-2 FFT as `fft::fft_inplace`, returns the block exponent it added
+fn fft_fixed(re: &mut [i32], im: &mut [i32], tw_re: &[i32], tw_im: &[i32]) -> u32 {
+    let n = re.len();
+    let mut j = 0usize;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j ^= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut exponent = 0;
+    let mut bits = magnitude_bits(re, im);
+    let mut len = 2usize;
+    while len <= n {
+        exponent += normalize(re, im, bits);
+        bits = 0;
+        let half = len / 2;
+        let stride = tw_re.len() * 2 / len;
+        let mut i = 0usize;
+        while i < n {
+            for j in 0..half {
+                let (w_re, w_im) = (tw_re[j * stride], tw_im[j * stride]);
+                let (b_re, b_im) = (re[i + j + half], im[i + j + half]);
+                let v_re = round_q30(mul(b_re, w_re) - mul(b_im, w_im));
+                let v_im = round_q30(mul(b_re, w_im) + mul(b_im, w_re));
+                let (u_re, u_im) = (re[i + j], im[i + j]);
+                re[i + j] = u_re + v_re;
+                im[i + j] = u_im + v_im;
+                re[i + j + half] = u_re - v_re;
+                im[i + j + half] = u_im - v_im;
+                // tracked here so the next pass doesn't need its own scan
+                bits |= (u_re + v_re).unsigned_abs()
+                    | (u_im + v_im).unsigned_abs()
+                    | (u_re - v_re).unsigned_abs()
+                    | (u_im - v_im).unsigned_abs();
+            }
+            i += len;
+        }
+        len <<= 1;
+    }
+    exponent + normalize(re, im, bits)
+}
+
+impl FftBackend for FixedFft {
+    fn new(size: usize, window: Window) -> Self {
+        let q15 = |w: f32| libm::roundf(w * i16::MAX as f32) as i16;
+        let q30 = |w: f32| libm::roundf(w * (1 << TWIDDLE_BITS) as f32) as i32;
+        let (twiddle_re, twiddle_im) = twiddles(size);
+        let window = window.coefficients(size);
+        // a sample times a Q15 coefficient is 2^30 at full scale
+        let scale = 1.0 / (i16::MAX as f32 * i16::MAX as f32 * coherent_gain(&window));
+        Self {
+            window: window.iter().map(|&w| q15(w)).collect(),
+            scale,
+            twiddle_re: twiddle_re.iter().map(|&w| q30(w)).collect(),
+            twiddle_im: twiddle_im.iter().map(|&w| q30(w)).collect(),
+            re: vec![0; size / 2].into_boxed_slice(),
+            im: vec![0; size / 2].into_boxed_slice(),
+        }
+    }
+
+    fn power(&mut self, samples: &[i16], power: &mut [f32]) {
+        let m = self.re.len();
+        // exact, and at most 2^30, the first pass shifts it down if it needs to
+        let window = |i: usize| samples[i] as i32 * self.window[i] as i32;
+        for k in 0..m {
+            self.re[k] = window(2 * k);
+            self.im[k] = window(2 * k + 1);
+        }
+        let (tw_re, tw_im) = (&self.twiddle_re[..], &self.twiddle_im[..]);
+        let (re, im) = (&mut self.re[..], &mut self.im[..]);
+        let mut exponent = fft_fixed(re, im, tw_re, tw_im);
+
+        // split the even and odd spectra as `fft::fft_real` does, halving
+        // E and O leaves room for E + w^k O
+        exponent += normalize(re, im, magnitude_bits(re, im));
+        let (z_re, z_im) = (re[0], im[0]);
+        re[0] = z_re + z_im;
+        im[0] = 0;
+        for k in 1..=m / 2 {
+            let (a_re, a_im) = (re[k], im[k]);
+            let (b_re, b_im) = (re[m - k], im[m - k]);
+            let e_re = (a_re + b_re) >> 1;
+            let e_im = (a_im - b_im) >> 1;
+            let o_re = (a_im + b_im) >> 1;
+            let o_im = (b_re - a_re) >> 1;
+            let (w_re, w_im) = (tw_re[k], tw_im[k]);
+            let t_re = round_q30(mul(o_re, w_re) - mul(o_im, w_im));
+            let t_im = round_q30(mul(o_re, w_im) + mul(o_im, w_re));
+            re[k] = e_re + t_re;
+            im[k] = e_im + t_im;
+            re[m - k] = e_re - t_re;
+            im[m - k] = t_im - e_im;
+        }
+
+        let scale = (1u64 << exponent) as f32 * self.scale;
+        for ((p, &re), &im) in power.iter_mut().zip(re.iter()).zip(im.iter()) {
+            let (re, im) = (re as f32 * scale, im as f32 * scale);
+            *p = re * re + im * im;
+        }
+    }
+}
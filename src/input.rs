@@ -0,0 +1,37 @@
+use psp::sys::{self, CtrlButtons, CtrlMode, SceCtrlData};
+
+/// Controller state with edge detection, polled once per frame
+pub struct Pad {
+    held: CtrlButtons,
+    pressed: CtrlButtons,
+}
+
+impl Pad {
+    pub fn new() -> Self {
+        unsafe {
+            sys::sceCtrlSetSamplingCycle(0);
+            sys::sceCtrlSetSamplingMode(CtrlMode::Analog);
+        }
+        Self {
+            held: CtrlButtons::empty(),
+            pressed: CtrlButtons::empty(),
+        }
+    }
+
+    pub fn poll(&mut self) {
+        let mut data = SceCtrlData::default();
+        unsafe { sys::sceCtrlPeekBufferPositive(&mut data, 1) };
+        self.pressed = data.buttons & !self.held;
+        self.held = data.buttons;
+    }
+
+    /// true on the frame the button went down
+    pub fn pressed(&self, b: CtrlButtons) -> bool {
+        self.pressed.contains(b)
+    }
+
+    #[allow(dead_code)]
+    pub fn held(&self, b: CtrlButtons) -> bool {
+        self.held.contains(b)
+    }
+}
@@ -0,0 +1,105 @@
+// minimal no_std stand-ins for std::io::{Read, Seek}
+// so the parsers/decoders don't care where the bytes come from
+
+extern crate alloc;
+use alloc::boxed::Box;
+
+use crate::error::{Error, SCE_ERROR_ERRNO_EINVAL};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+pub trait ByteRead {
+    /// Read up to out.len() bytes into out, returns 0 at end of stream
+    fn read(&mut self, out: &mut [u8]) -> Result<usize, Error>;
+
+    /// Keep reading until `out` is full or the stream ends.
+    /// Returns the number of bytes read
+    fn read_full(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+        let mut total = 0usize;
+        while total < out.len() {
+            let n = self.read(&mut out[total..])?;
+            if n == 0 {
+                break;
+            }
+            total += n;
+        }
+        Ok(total)
+    }
+}
+
+pub trait ByteSeek {
+    /// Returns the new absolute position
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error>;
+
+    /// Get stream size without changing the current position.
+    fn size(&mut self) -> Result<u64, Error> {
+        let cur = self.seek(SeekFrom::Current(0))?;
+        let end = self.seek(SeekFrom::End(0))?;
+        self.seek(SeekFrom::Start(cur))?;
+        Ok(end)
+    }
+}
+
+/// Anything the decoder can stream from
+pub trait ByteStream: ByteRead + ByteSeek {}
+
+impl<T: ByteRead + ByteSeek + ?Sized> ByteStream for T {}
+
+impl<T: ByteRead + ?Sized> ByteRead for Box<T> {
+    fn read(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+        (**self).read(out)
+    }
+}
+
+impl<T: ByteSeek + ?Sized> ByteSeek for Box<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        (**self).seek(pos)
+    }
+}
+
+/// Cursor over an in-memory buffer (e.g. an `include_bytes!` asset)
+pub struct SliceReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+}
+
+impl ByteRead for SliceReader<'_> {
+    fn read(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+        let start = self.pos.min(self.data.len());
+        let n = out.len().min(self.data.len() - start);
+        out[..n].copy_from_slice(&self.data[start..start + n]);
+        self.pos = start + n;
+        Ok(n)
+    }
+}
+
+impl ByteSeek for SliceReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let target = match pos {
+            SeekFrom::Start(off) => off as i64,
+            SeekFrom::End(off) => self.data.len() as i64 + off,
+            SeekFrom::Current(off) => self.pos as i64 + off,
+        };
+        // like lseek, seeking past the end is fine, before the start is not
+        if target < 0 {
+            return Err(Error::Io(SCE_ERROR_ERRNO_EINVAL));
+        }
+        self.pos = target as usize;
+        Ok(self.pos as u64)
+    }
+
+    fn size(&mut self) -> Result<u64, Error> {
+        Ok(self.data.len() as u64)
+    }
+}
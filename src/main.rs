@@ -3,19 +3,45 @@
 
 extern crate alloc;
 
+mod bands;
+mod decoder;
+mod dsp;
+mod error;
 mod fft;
+#[cfg(feature = "fixed-fft")]
+mod fft_fixed;
+mod input;
+mod io;
+mod menu;
+mod mixer;
 mod mp3;
+mod overlay;
+mod playlist;
+mod readahead;
+mod settings;
+mod stats;
+mod tags;
 mod utils;
+mod vbr;
 
 use alloc::boxed::Box;
+use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
-use core::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicPtr, Ordering};
 use core::{ffi::c_void, ptr};
-use fft::Analyzer;
+use dsp::eq::EqDesign;
+use dsp::loudness::Loudness;
+use dsp::meter::{ChannelLevel, METER_FLOOR_DB};
+use fft::{Analyzer, AnalyzerSettings};
+use input::Pad;
+use menu::Menu;
+use mixer::{Clip, Mixer, SAMPLING_RATE};
 use mp3::Mp3Player;
+use playlist::Playlist;
 use psp::sys;
 use psp::sys::ClearBuffer;
+use psp::sys::CtrlButtons;
 use psp::sys::GuContextType;
 use psp::sys::GuPrimitive;
 use psp::sys::GuState;
@@ -24,12 +50,16 @@ use psp::sys::GuSyncMode;
 use psp::sys::VertexType;
 use psp::vram_alloc::get_vram_allocator;
 use psp::{Align16, BUF_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH};
+use settings::Settings;
 
 // static GU list buffer
 static mut LIST: Align16<[u32; 0x40000]> = Align16([0; 0x40000]);
 
-// pointer to 1x1 white texture stored in VRAM (set in init_gu)
-static mut WHITE_VRAM_PTR: *mut core::ffi::c_void = core::ptr::null_mut();
+// radial gradient texture stored in VRAM (set in init_gu), white fading
+// out to the edge, for the smear trails and bar heads
+static mut GLOW_VRAM_PTR: *mut core::ffi::c_void = core::ptr::null_mut();
+/// Side of the gradient texture in texels
+const GLOW_SIZE: u32 = 32;
 
 #[repr(C, align(4))]
 struct ColVertex {
@@ -39,13 +69,54 @@ struct ColVertex {
     z: f32,
 }
 
-// persistent CPU-side vertex buffer to avoid calling `sceGuGetMemory` each frame
-static mut VERTEX_BUFFER: Align16<[u8; 16 * (64 * 2)]> = Align16([0; 16 * (64 * 2)]);
+#[repr(C, align(4))]
+struct TexVertex {
+    u: f32,
+    v: f32,
+    color: u32,
+    x: f32,
+    y: f32,
+    z: f32,
+}
 
-const SPECTRUM_SIZE: usize = 64;
+// persistent CPU-side vertex buffer to avoid calling `sceGuGetMemory` each frame
+static mut VERTEX_BUFFER: Align16<[u8; 16 * (SPECTRUM_SIZE * 2)]> =
+    Align16([0; 16 * (SPECTRUM_SIZE * 2)]);
+
+/// Most bars on screen, enough for the classic layout at the largest FFT
+const SPECTRUM_SIZE: usize = 128;
+// smear trail and head sprites, one each per bar
+static mut TRAIL_VERTEX_BUFFER: Align16<[u8; 24 * (SPECTRUM_SIZE * 2)]> =
+    Align16([0; 24 * (SPECTRUM_SIZE * 2)]);
+static mut HEAD_VERTEX_BUFFER: Align16<[u8; 24 * (SPECTRUM_SIZE * 2)]> =
+    Align16([0; 24 * (SPECTRUM_SIZE * 2)]);
+// line strip for the EQ response drawn over the bars
+static mut EQ_VERTEX_BUFFER: Align16<[u8; 16 * SPECTRUM_SIZE]> = Align16([0; 16 * SPECTRUM_SIZE]);
+// track, loop region and played sprites of the progress bar
+static mut PROGRESS_VERTEX_BUFFER: Align16<[u8; 16 * 6]> = Align16([0; 16 * 6]);
+// loudness screen: background, fill and target tick for 3 bars
+static mut LOUDNESS_VERTEX_BUFFER: Align16<[u8; 16 * 18]> = Align16([0; 16 * 18]);
+// stereo level meter, 5 sprites per channel
+static mut METER_VERTEX_BUFFER: Align16<[u8; 16 * 20]> = Align16([0; 16 * 20]);
 static mut SPECTRUM: Align16<[f32; SPECTRUM_SIZE]> = Align16([0.0; SPECTRUM_SIZE]);
+// where each bar's trail reaches, published with SPECTRUM
+static mut SMEAR: Align16<[f32; SPECTRUM_SIZE]> = Align16([0.0; SPECTRUM_SIZE]);
 static SPECTRUM_GEN: AtomicI32 = AtomicI32::new(0);
 static SPECTRUM_STOP: AtomicBool = AtomicBool::new(false);
+/// Settings for the analyzer to pick up before its next frame
+static ANALYZER_SETTINGS: AtomicPtr<AnalyzerSettings> = AtomicPtr::new(ptr::null_mut());
+
+fn set_analyzer_settings(settings: AnalyzerSettings) {
+    let old = ANALYZER_SETTINGS.swap(Box::into_raw(Box::new(settings)), Ordering::AcqRel);
+    if !old.is_null() {
+        unsafe { drop(Box::from_raw(old)) };
+    }
+}
+
+fn take_analyzer_settings() -> Option<AnalyzerSettings> {
+    let p = ANALYZER_SETTINGS.swap(ptr::null_mut(), Ordering::AcqRel);
+    (!p.is_null()).then(|| *unsafe { Box::from_raw(p) })
+}
 
 psp::module!("Musializer PSP", 1, 0);
 
@@ -59,12 +130,25 @@ fn psp_main() {
     psp::dprintln!("musializer-psp: starting MP3 player integration test");
 
     let path = "ms0:/PSP/GAME/Project/assets/sounds/mp3/compressed/ost_01_stripped_5s.mp3";
+    // the rest of the directory plays after it
+    let mut playlist = Playlist::from_file(path);
+    let first = playlist.next().unwrap_or(path);
+
+    // owns the audio output, the player and UI sounds are voices on it
+    let mixer = match Mixer::new() {
+        Ok(m) => m,
+        Err(e) => {
+            psp::dprintln!("Failed to start mixer: {}", e);
+            return;
+        }
+    };
+    let click = click_sound(SAMPLING_RATE);
 
-    match Mp3Player::open(path) {
+    match Mp3Player::open(first, &mixer) {
         Ok(mut player) => {
             psp::dprintln!("MP3 player started");
             // Create Analyzer on heap and start FFT worker thread.
-            let analyzer = Box::new(Analyzer::new());
+            let analyzer: Box<Analyzer> = Box::new(Analyzer::new(&AnalyzerSettings::default()));
             let analyzer_ptr = Box::into_raw(analyzer);
 
             let shared_ptr = player.raw_shared_ptr();
@@ -95,29 +179,93 @@ fn psp_main() {
                 };
             }
 
-            // precompute xs
             let margin = 20.0f32;
-            let width_avail = SCREEN_WIDTH as f32 - margin * 2.0f32;
-            let cell_w = width_avail / (SPECTRUM_SIZE as f32);
-            let precomputed_xs: [f32; SPECTRUM_SIZE] = {
-                let mut xs = [0.0f32; SPECTRUM_SIZE];
-                for i in 0..SPECTRUM_SIZE {
-                    xs[i] = margin + i as f32 * cell_w;
-                }
-                xs
-            };
+
+            let mut pad = Pad::new();
+            // SELECT toggles the audio telemetry overlay
+            let mut show_stats = false;
+            // R swaps the spectrum for the loudness meter, CIRCLE resets it there
+            let mut show_loudness = false;
+            // D-pad up/down changes volume, TRIANGLE toggles mute
+            const VOLUME_STEP: f32 = 0.05;
+
+            let mut settings = Settings::default();
+            let mut menu = Menu::new();
+            apply_settings(&player, &mixer, &settings);
+            let mut bars = Bars::new(&settings.analyzer(), margin);
+            let mut eq_design = settings.eq_design();
+            let mut eq_db = eq_curve(&eq_design, &bars);
 
             // local render loop reads SPECTRUM written by FFT thread
             loop {
+                pad.poll();
+                if pad.pressed(CtrlButtons::SELECT) {
+                    show_stats = !show_stats;
+                }
+                if pad.pressed(CtrlButtons::RTRIGGER) {
+                    show_loudness = !show_loudness;
+                }
+                if menu.update(&pad, &mut settings) {
+                    apply_settings(&player, &mixer, &settings);
+                    bars = Bars::new(&settings.analyzer(), margin);
+                    eq_design = settings.eq_design();
+                    eq_db = eq_curve(&eq_design, &bars);
+                    let _ = mixer.play(Box::new(Clip::new(click.clone())), 0.5, 0.0);
+                }
+                if !menu.open {
+                    if pad.pressed(CtrlButtons::UP) {
+                        player.set_volume(player.volume() + VOLUME_STEP);
+                    }
+                    if pad.pressed(CtrlButtons::DOWN) {
+                        player.set_volume(player.volume() - VOLUME_STEP);
+                    }
+                    if pad.pressed(CtrlButtons::TRIANGLE) {
+                        if player.is_muted() {
+                            player.unmute();
+                        } else {
+                            player.mute();
+                        }
+                    }
+                    // CROSS pauses, SQUARE sets A, then B, then clears the loop
+                    if pad.pressed(CtrlButtons::CROSS) {
+                        if player.is_paused() {
+                            player.resume();
+                        } else {
+                            player.pause();
+                        }
+                    }
+                    if pad.pressed(CtrlButtons::SQUARE) {
+                        player.mark_loop();
+                    }
+                    if show_loudness && pad.pressed(CtrlButtons::CIRCLE) {
+                        player.reset_loudness();
+                    }
+                }
+
+                // keep one track queued so the player can crossfade into it
+                if !player.has_queued()
+                    && let Some(next) = playlist.next()
+                    && let Err(e) = player.enqueue(next)
+                {
+                    psp::dprintln!("Failed to queue {}: {}", next, e);
+                }
+
+                if let Err(e) = mixer.tick() {
+                    psp::dprintln!("Mixer error: {}", e);
+                    break;
+                }
+
                 match player.tick() {
                     Ok(true) => {
                         // copy shared spectrum snapshot into local fixed-size buffer
-                        let display_m = SPECTRUM_SIZE;
+                        let display_m = bars.count;
                         let mut local = [0.0f32; SPECTRUM_SIZE];
+                        let mut smear = [0.0f32; SPECTRUM_SIZE];
                         let _gen = SPECTRUM_GEN.load(Ordering::Acquire);
                         unsafe {
-                            for i in 0..display_m {
-                                local[i] = SPECTRUM.0[i];
+                            for (i, v) in local.iter_mut().take(display_m).enumerate() {
+                                *v = SPECTRUM.0[i];
+                                smear[i] = SMEAR.0[i];
                             }
                         }
 
@@ -135,7 +283,6 @@ fn psp_main() {
 
                             sys::sceGuDisable(GuState::Texture2D);
                             let bottom = SCREEN_HEIGHT as f32 - 40.0f32;
-                            let width_avail = SCREEN_WIDTH as f32 - margin * 2.0f32;
                             let max_h = (SCREEN_HEIGHT as f32) * 0.5f32;
 
                             let verts_count = (display_m * 2) as i32;
@@ -143,10 +290,10 @@ fn psp_main() {
                             let vertices = core::ptr::addr_of_mut!(VERTEX_BUFFER.0) as *mut u8
                                 as *mut ColVertex;
 
-                            for i in 0..display_m {
-                                let t = local[i] as f32;
-                                let bar_h = (t.max(0.0) * max_h) as f32;
-                                let x = precomputed_xs[i];
+                            for (i, (&t, &x)) in
+                                local.iter().zip(&bars.xs).take(display_m).enumerate()
+                            {
+                                let bar_h = t.max(0.0) * max_h;
                                 let y = bottom - bar_h;
 
                                 let base = (i * 2) as isize;
@@ -155,8 +302,8 @@ fn psp_main() {
                                     vertices.offset(base),
                                     ColVertex {
                                         color,
-                                        x: x,
-                                        y: y,
+                                        x,
+                                        y,
                                         z: 0.0,
                                     },
                                 );
@@ -164,31 +311,112 @@ fn psp_main() {
                                     vertices.offset(base + 1),
                                     ColVertex {
                                         color,
-                                        x: x + cell_w * 0.9,
+                                        x: x + bars.cell_w * 0.9,
                                         y: y + bar_h,
                                         z: 0.0,
                                     },
                                 );
                             }
 
-                            sys::sceGuDrawArray(
-                                GuPrimitive::Sprites,
-                                VertexType::COLOR_8888
-                                    | VertexType::VERTEX_32BITF
-                                    | VertexType::TRANSFORM_2D,
-                                verts_count,
-                                ptr::null_mut(),
-                                vertices as *const c_void,
+                            let loudness = player.loudness();
+                            if show_loudness {
+                                draw_loudness_bars(LOUDNESS_BARS_X, bottom, max_h, &loudness);
+                            } else {
+                                draw_trails(&local, &smear, &bars, bottom, max_h);
+                                sys::sceGuDrawArray(
+                                    GuPrimitive::Sprites,
+                                    VertexType::COLOR_8888
+                                        | VertexType::VERTEX_32BITF
+                                        | VertexType::TRANSFORM_2D,
+                                    verts_count,
+                                    ptr::null_mut(),
+                                    vertices as *const c_void,
+                                );
+                                draw_heads(&local, &bars, bottom, max_h);
+
+                                if !eq_design.is_flat() {
+                                    draw_eq_curve(&eq_db, &bars, bottom, max_h);
+                                }
+                            }
+
+                            draw_progress(
+                                margin,
+                                SCREEN_WIDTH as f32 - margin,
+                                SCREEN_HEIGHT as f32 - 28.0,
+                                player.position_ms(),
+                                player.duration_ms(),
+                                player.loop_points(),
+                            );
+
+                            draw_meter(
+                                SCREEN_WIDTH as f32 - margin - 160.0,
+                                SCREEN_WIDTH as f32 - margin,
+                                SCREEN_HEIGHT as f32 - 18.0,
+                                &player.levels(),
                             );
 
                             sys::sceGuFinish();
                             sys::sceGuSync(GuSyncMode::Finish, GuSyncBehavior::Wait);
+
+                            if show_stats {
+                                let y = overlay::draw_player_stats(8, 8, &player.stats());
+                                let y = overlay::draw_mixer_stats(8, y, &mixer.stats());
+                                overlay::print(
+                                    8,
+                                    y,
+                                    0xFFFFFFFF,
+                                    format_args!(
+                                        "vol {:.0}%{} rg {:+.1} dB track {}",
+                                        player.volume() * 100.0,
+                                        if player.is_muted() { " (muted)" } else { "" },
+                                        player.replaygain_db(),
+                                        player.track_number() + 1
+                                    ),
+                                );
+                            }
+                            overlay::draw_time(
+                                8,
+                                SCREEN_HEIGHT as i32 - 16,
+                                player.position_ms(),
+                                player.duration_ms(),
+                                player.speed(),
+                                player.is_paused(),
+                            );
+                            if show_loudness {
+                                overlay::draw_loudness(
+                                    LOUDNESS_BARS_X as i32 + LOUDNESS_BARS_W as i32 + 24,
+                                    (bottom - max_h) as i32,
+                                    &loudness,
+                                );
+                            }
+                            menu.draw(256, 8, &settings);
+                            overlay::flush();
+
                             sys::sceDisplayWaitVblankStart();
                             sys::sceGuSwapBuffers();
                         }
                     }
                     Ok(false) => {
                         psp::dprintln!("MP3 finished");
+                        let stats = player.stats();
+                        let mix = mixer.stats();
+                        psp::dprintln!(
+                            "{} buffers, {} late, {} underruns, {} decode errors",
+                            stats.buffers,
+                            mix.late_buffers,
+                            mix.underruns,
+                            stats.decode_errors
+                        );
+                        if let Some(io) = stats.io {
+                            psp::dprintln!(
+                                "io: {} hits, {} stalls ({} us, max {} us), {} bytes",
+                                io.hits,
+                                io.stalls,
+                                io.stall_us,
+                                io.max_stall_us,
+                                io.bytes_read
+                            );
+                        }
                         SPECTRUM_STOP.store(true, Ordering::Relaxed);
                         if fft_thid.0 >= 0 {
                             let _ =
@@ -197,8 +425,8 @@ fn psp_main() {
                         }
                         break;
                     }
-                    Err(_) => {
-                        psp::dprintln!("MP3 error");
+                    Err(e) => {
+                        psp::dprintln!("MP3 error: {}", e);
                         break;
                     }
                 }
@@ -206,14 +434,436 @@ fn psp_main() {
                 // unsafe { sys::sceKernelDelayThreadCB(5000) };
             }
         }
-        Err(_) => {
-            psp::dprintln!("Failed to start MP3");
+        Err(e) => {
+            psp::dprintln!("Failed to start MP3: {}", e);
         }
     }
 
     psp::dprintln!("musializer-psp: exiting");
 }
 
+/// Push settings that live in the audio threads over to the player and mixer
+fn apply_settings(player: &Mp3Player, mixer: &Mixer, settings: &Settings) {
+    player.set_replaygain_mode(settings.replaygain);
+    player.set_crossfade(settings.crossfade_secs);
+    player.set_resample_mode(settings.resample);
+    mixer.set_master(&settings.master());
+    player.set_master(&settings.master());
+    player.set_eq(settings.eq_design());
+    player.set_speed(settings.speed());
+    player.set_pitch(settings.pitch);
+    player.set_karaoke(settings.karaoke);
+    player.set_ballistics(settings.meter);
+    set_analyzer_settings(settings.analyzer());
+}
+
+/// Where the spectrum bars go for the analyzer's band layout
+struct Bars {
+    count: usize,
+    cell_w: f32,
+    xs: [f32; SPECTRUM_SIZE],
+    /// center frequency of each bar's band
+    freqs: [f32; SPECTRUM_SIZE],
+}
+
+impl Bars {
+    /// Spread across the screen with `margin` on either side
+    fn new(settings: &AnalyzerSettings, margin: f32) -> Self {
+        let centers = fft::band_centers(settings);
+        let count = centers.len().min(SPECTRUM_SIZE);
+        let cell_w = (SCREEN_WIDTH as f32 - margin * 2.0) / count.max(1) as f32;
+        Self {
+            count,
+            cell_w,
+            xs: core::array::from_fn(|i| margin + i as f32 * cell_w),
+            freqs: core::array::from_fn(|i| centers.get(i).copied().unwrap_or(0.0)),
+        }
+    }
+}
+
+/// EQ gain in dB at each bar's frequency
+fn eq_curve(design: &EqDesign, bars: &Bars) -> [f32; SPECTRUM_SIZE] {
+    core::array::from_fn(|i| design.response_db(bars.freqs[i], SAMPLING_RATE))
+}
+
+/// Point the GE at the gradient texture, tinted by the vertex colour
+unsafe fn bind_glow() {
+    unsafe {
+        sys::sceGuEnable(GuState::Texture2D);
+        sys::sceGuTexMode(sys::TexturePixelFormat::Psm8888, 0, 0, 0);
+        sys::sceGuTexImage(
+            sys::MipmapLevel::None,
+            GLOW_SIZE as i32,
+            GLOW_SIZE as i32,
+            GLOW_SIZE as i32,
+            GLOW_VRAM_PTR,
+        );
+        sys::sceGuTexFunc(
+            sys::TextureEffect::Modulate,
+            sys::TextureColorComponent::Rgba,
+        );
+        sys::sceGuTexFilter(sys::TextureFilter::Linear, sys::TextureFilter::Linear);
+        sys::sceGuTexWrap(sys::GuTexWrapMode::Clamp, sys::GuTexWrapMode::Clamp);
+    }
+}
+
+unsafe fn draw_glow_sprites(vertices: *const TexVertex, count: usize) {
+    unsafe {
+        bind_glow();
+        sys::sceGuDrawArray(
+            GuPrimitive::Sprites,
+            VertexType::TEXTURE_32BITF
+                | VertexType::COLOR_8888
+                | VertexType::VERTEX_32BITF
+                | VertexType::TRANSFORM_2D,
+            count as i32,
+            ptr::null_mut(),
+            vertices as *const c_void,
+        );
+        sys::sceGuDisable(GuState::Texture2D);
+    }
+}
+
+/// Radius of the heads, and half the width of the trails
+fn glow_radius(bars: &Bars) -> f32 {
+    bars.cell_w.max(2.0)
+}
+
+/// Each bar's smear trail, from its top to where the smear has got to.
+/// Drawn before the bars so only what trails above a falling bar shows
+unsafe fn draw_trails(
+    heights: &[f32; SPECTRUM_SIZE],
+    smear: &[f32; SPECTRUM_SIZE],
+    bars: &Bars,
+    bottom: f32,
+    max_h: f32,
+) {
+    const COLOR: u32 = 0x80FFFFFF; // half transparent white, ABGR
+    let vertices =
+        unsafe { core::ptr::addr_of_mut!(TRAIL_VERTEX_BUFFER.0) } as *mut u8 as *mut TexVertex;
+    let half_w = glow_radius(bars) * 0.5;
+    // across the gradient's middle row, so the sides are soft
+    let v = GLOW_SIZE as f32 * 0.5;
+    let mut count = 0;
+    for i in 0..bars.count {
+        let top = bottom - heights[i].max(0.0) * max_h;
+        let tail = bottom - smear[i].max(0.0) * max_h;
+        if (tail - top).abs() < 1.0 {
+            continue;
+        }
+        let cx = bars.xs[i] + bars.cell_w * 0.45;
+        unsafe {
+            ptr::write(
+                vertices.add(count),
+                TexVertex {
+                    u: 0.0,
+                    v,
+                    color: COLOR,
+                    x: cx - half_w,
+                    y: top.min(tail),
+                    z: 0.0,
+                },
+            );
+            ptr::write(
+                vertices.add(count + 1),
+                TexVertex {
+                    u: GLOW_SIZE as f32,
+                    v,
+                    color: COLOR,
+                    x: cx + half_w,
+                    y: top.max(tail),
+                    z: 0.0,
+                },
+            );
+        }
+        count += 2;
+    }
+    if count > 0 {
+        unsafe { draw_glow_sprites(vertices, count) };
+    }
+}
+
+/// A soft round head on each bar's top
+unsafe fn draw_heads(heights: &[f32; SPECTRUM_SIZE], bars: &Bars, bottom: f32, max_h: f32) {
+    const COLOR: u32 = 0xFFFFFFFF;
+    let vertices =
+        unsafe { core::ptr::addr_of_mut!(HEAD_VERTEX_BUFFER.0) } as *mut u8 as *mut TexVertex;
+    let r = glow_radius(bars);
+    for (i, h) in heights.iter().take(bars.count).enumerate() {
+        let cx = bars.xs[i] + bars.cell_w * 0.45;
+        let cy = bottom - h.max(0.0) * max_h;
+        unsafe {
+            ptr::write(
+                vertices.add(i * 2),
+                TexVertex {
+                    u: 0.0,
+                    v: 0.0,
+                    color: COLOR,
+                    x: cx - r,
+                    y: cy - r,
+                    z: 0.0,
+                },
+            );
+            ptr::write(
+                vertices.add(i * 2 + 1),
+                TexVertex {
+                    u: GLOW_SIZE as f32,
+                    v: GLOW_SIZE as f32,
+                    color: COLOR,
+                    x: cx + r,
+                    y: cy + r,
+                    z: 0.0,
+                },
+            );
+        }
+    }
+    unsafe { draw_glow_sprites(vertices, bars.count * 2) };
+}
+
+/// Draw the EQ response as a line over the bars, 0 dB halfway up the bar area
+unsafe fn draw_eq_curve(db: &[f32; SPECTRUM_SIZE], bars: &Bars, bottom: f32, max_h: f32) {
+    const RANGE_DB: f32 = 18.0;
+    let zero_y = bottom - max_h * 0.5;
+    let vertices =
+        unsafe { core::ptr::addr_of_mut!(EQ_VERTEX_BUFFER.0) } as *mut u8 as *mut ColVertex;
+    for (i, d) in db.iter().take(bars.count).enumerate() {
+        let d = d.clamp(-RANGE_DB, RANGE_DB);
+        unsafe {
+            ptr::write(
+                vertices.add(i),
+                ColVertex {
+                    color: 0xC040FFFF, // translucent yellow, ABGR
+                    x: bars.xs[i] + bars.cell_w * 0.45,
+                    y: zero_y - d / RANGE_DB * max_h * 0.5,
+                    z: 0.0,
+                },
+            );
+        }
+    }
+    unsafe {
+        sys::sceGuDrawArray(
+            GuPrimitive::LineStrip,
+            VertexType::COLOR_8888 | VertexType::VERTEX_32BITF | VertexType::TRANSFORM_2D,
+            bars.count as i32,
+            ptr::null_mut(),
+            vertices as *const c_void,
+        );
+    }
+}
+
+/// Progress bar from `left` to `right`, with the A-B loop region over it
+/// (dimmer while only A is set)
+unsafe fn draw_progress(
+    left: f32,
+    right: f32,
+    y: f32,
+    position_ms: u32,
+    duration_ms: Option<u32>,
+    ab: Option<(u32, Option<u32>)>,
+) {
+    const HEIGHT: f32 = 4.0;
+    let Some(duration) = duration_ms.filter(|&d| d > 0) else {
+        return;
+    };
+    let x_at = |ms: u32| left + (right - left) * (ms as f32 / duration as f32).min(1.0);
+
+    let mut sprites: [(f32, f32, u32); 3] =
+        [(left, right, 0xFF404040), (0.0, 0.0, 0), (0.0, 0.0, 0)];
+    let mut count = 1;
+    if let Some((a, b)) = ab {
+        let (end, color) = match b {
+            Some(b) => (b, 0xC040C040),
+            None => (position_ms.max(a), 0x8040C040),
+        };
+        sprites[count] = (x_at(a), x_at(end).max(x_at(a) + 1.0), color);
+        count += 1;
+    }
+    sprites[count] = (left, x_at(position_ms), 0xFFFFFFFF);
+    count += 1;
+
+    let vertices =
+        unsafe { core::ptr::addr_of_mut!(PROGRESS_VERTEX_BUFFER.0) } as *mut u8 as *mut ColVertex;
+    for (i, &(x0, x1, color)) in sprites[..count].iter().enumerate() {
+        unsafe {
+            ptr::write(
+                vertices.add(i * 2),
+                ColVertex {
+                    color,
+                    x: x0,
+                    y,
+                    z: 0.0,
+                },
+            );
+            ptr::write(
+                vertices.add(i * 2 + 1),
+                ColVertex {
+                    color,
+                    x: x1,
+                    y: y + HEIGHT,
+                    z: 0.0,
+                },
+            );
+        }
+    }
+    unsafe {
+        // blending is on from init_gu, the loop region is translucent
+        sys::sceGuDrawArray(
+            GuPrimitive::Sprites,
+            VertexType::COLOR_8888 | VertexType::VERTEX_32BITF | VertexType::TRANSFORM_2D,
+            (count * 2) as i32,
+            ptr::null_mut(),
+            vertices as *const c_void,
+        );
+    }
+}
+
+/// Loudness screen layout: where the M/S/I bars start and how wide they are together
+const LOUDNESS_BARS_X: f32 = 40.0;
+const LOUDNESS_BARS_W: f32 = 3.0 * 40.0 + 2.0 * 16.0;
+
+/// Momentary, short-term and integrated loudness as bars on the EBU +18
+/// scale (-41..-5 LUFS), with a tick at the -23 LUFS target
+unsafe fn draw_loudness_bars(left: f32, bottom: f32, height: f32, l: &Loudness) {
+    const LOW: f32 = -41.0;
+    const HIGH: f32 = -5.0;
+    const TARGET: f32 = -23.0;
+    const BAR_W: f32 = 40.0;
+    const GAP: f32 = 16.0;
+    let y_at = |lufs: f32| {
+        let t = if lufs.is_finite() {
+            ((lufs - LOW) / (HIGH - LOW)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        bottom - height * t
+    };
+
+    let vertices =
+        unsafe { core::ptr::addr_of_mut!(LOUDNESS_VERTEX_BUFFER.0) } as *mut u8 as *mut ColVertex;
+    let mut n = 0;
+    let mut sprite = |x0: f32, y0: f32, x1: f32, y1: f32, color: u32| {
+        for (x, y) in [(x0, y0), (x1, y1)] {
+            unsafe {
+                ptr::write(
+                    vertices.add(n),
+                    ColVertex {
+                        color,
+                        x,
+                        y,
+                        z: 0.0,
+                    },
+                )
+            };
+            n += 1;
+        }
+    };
+
+    // ABGR: green, light blue, yellow
+    let bars = [
+        (l.momentary, 0xFF40C040),
+        (l.short_term, 0xFFFFC040),
+        (l.integrated, 0xFF40E0FF),
+    ];
+    let target = y_at(TARGET);
+    for (i, &(lufs, color)) in bars.iter().enumerate() {
+        let x = left + i as f32 * (BAR_W + GAP);
+        sprite(x, bottom - height, x + BAR_W, bottom, 0xFF303030);
+        sprite(x, y_at(lufs), x + BAR_W, bottom, color);
+        sprite(
+            x - 2.0,
+            target - 1.0,
+            x + BAR_W + 2.0,
+            target + 1.0,
+            0xFFFFFFFF,
+        );
+    }
+
+    unsafe {
+        sys::sceGuDrawArray(
+            GuPrimitive::Sprites,
+            VertexType::COLOR_8888 | VertexType::VERTEX_32BITF | VertexType::TRANSFORM_2D,
+            n as i32,
+            ptr::null_mut(),
+            vertices as *const c_void,
+        );
+    }
+}
+
+/// Stereo level meter from `left` to `right`, one bar per channel starting
+/// at `top`: RMS solid, peak dimmer on top of it, peak hold as a tick and a
+/// clip light at the end
+unsafe fn draw_meter(left: f32, right: f32, top: f32, levels: &[ChannelLevel; 2]) {
+    const HEIGHT: f32 = 5.0;
+    const GAP: f32 = 1.0;
+    const CLIP_W: f32 = 6.0;
+    let bar_right = right - CLIP_W - 2.0;
+    let x_at = |db: f32| {
+        let t = (db - METER_FLOOR_DB) / -METER_FLOOR_DB;
+        left + (bar_right - left) * t.clamp(0.0, 1.0)
+    };
+
+    let vertices =
+        unsafe { core::ptr::addr_of_mut!(METER_VERTEX_BUFFER.0) } as *mut u8 as *mut ColVertex;
+    let mut n = 0;
+    let mut sprite = |x0: f32, y0: f32, x1: f32, y1: f32, color: u32| {
+        for (x, y) in [(x0, y0), (x1, y1)] {
+            unsafe {
+                ptr::write(
+                    vertices.add(n),
+                    ColVertex {
+                        color,
+                        x,
+                        y,
+                        z: 0.0,
+                    },
+                )
+            };
+            n += 1;
+        }
+    };
+
+    for (c, l) in levels.iter().enumerate() {
+        let y = top + c as f32 * (HEIGHT + GAP);
+        // colors are ABGR
+        let hot = if l.peak_db > -6.0 {
+            0xFF40C0FF
+        } else {
+            0xFF40C040
+        };
+        sprite(left, y, bar_right, y + HEIGHT, 0xFF303030);
+        sprite(left, y, x_at(l.peak_db), y + HEIGHT, hot & 0x80FFFFFF);
+        sprite(left, y, x_at(l.rms_db), y + HEIGHT, hot);
+        let hold = x_at(l.hold_db);
+        sprite(hold - 1.0, y, hold + 1.0, y + HEIGHT, 0xFFFFFFFF);
+        let clip = if l.clip { 0xFF2020FF } else { 0xFF202040 };
+        sprite(right - CLIP_W, y, right, y + HEIGHT, clip);
+    }
+
+    unsafe {
+        sys::sceGuDrawArray(
+            GuPrimitive::Sprites,
+            VertexType::COLOR_8888 | VertexType::VERTEX_32BITF | VertexType::TRANSFORM_2D,
+            n as i32,
+            ptr::null_mut(),
+            vertices as *const c_void,
+        );
+    }
+}
+
+/// Short decaying blip for menu feedback, interleaved stereo
+fn click_sound(sampling_rate: u32) -> Arc<[i16]> {
+    let frames = sampling_rate as usize / 50; // 20ms
+    let mut samples = Vec::with_capacity(frames * 2);
+    for i in 0..frames {
+        let t = i as f32 / sampling_rate as f32;
+        let env = 1.0 - i as f32 / frames as f32;
+        let s = libm::sinf(2.0 * core::f32::consts::PI * 2000.0 * t) * env * env * 6000.0;
+        samples.push(s as i16);
+        samples.push(s as i16);
+    }
+    samples.into()
+}
+
 unsafe fn init_gu() {
     let allocator = get_vram_allocator().unwrap();
 
@@ -233,12 +883,24 @@ unsafe fn init_gu() {
         psp::sys::TexturePixelFormat::Psm4444,
     );
 
-    let white_tex =
-        allocator.alloc_texture_pixels(1u32, 1u32, psp::sys::TexturePixelFormat::Psm8888);
+    let glow_tex =
+        allocator.alloc_texture_pixels(GLOW_SIZE, GLOW_SIZE, psp::sys::TexturePixelFormat::Psm8888);
     unsafe {
-        let p_direct = white_tex.as_mut_ptr_direct_to_vram() as *mut u32;
-        p_direct.write_volatile(0xFFFFFFFFu32);
-        WHITE_VRAM_PTR = white_tex.as_mut_ptr_from_zero() as *mut c_void;
+        let p_direct = glow_tex.as_mut_ptr_direct_to_vram() as *mut u32;
+        let half = GLOW_SIZE as f32 * 0.5;
+        for y in 0..GLOW_SIZE {
+            for x in 0..GLOW_SIZE {
+                // distance from the centre, 1 at the edge
+                let dx = (x as f32 + 0.5 - half) / half;
+                let dy = (y as f32 + 0.5 - half) / half;
+                let fade = (1.0 - libm::sqrtf(dx * dx + dy * dy)).max(0.0);
+                let alpha = (fade * fade * 255.0) as u32;
+                p_direct
+                    .add((y * GLOW_SIZE + x) as usize)
+                    .write_volatile(alpha << 24 | 0x00FFFFFF);
+            }
+        }
+        GLOW_VRAM_PTR = glow_tex.as_mut_ptr_from_zero() as *mut c_void;
     }
 
     unsafe {
@@ -293,25 +955,42 @@ extern "C" fn fft_thread_main(_args: usize, argp: *mut c_void) -> i32 {
     let shared_ptr = args_box.shared_ptr;
     let analyzer_ptr = args_box.analyzer as *mut Analyzer;
 
-    let mut samples = vec![0.0f32; fft::FFT_SIZE].into_boxed_slice();
+    // interleaved stereo, the analyzer downmixes it
+    let mut samples = vec![0i16; unsafe { (*analyzer_ptr).size() } * 2].into_boxed_slice();
+    let mut last_us = unsafe { sys::sceKernelGetSystemTimeWide() };
 
     loop {
         if SPECTRUM_STOP.load(Ordering::Relaxed) {
             break;
         }
 
+        let analyzer = unsafe { &mut *analyzer_ptr };
+        // reconfigure here, between frames, nothing else touches the analyzer
+        if let Some(settings) = take_analyzer_settings() {
+            analyzer.configure(&settings);
+            if samples.len() != analyzer.size() * 2 {
+                samples = vec![0i16; analyzer.size() * 2].into_boxed_slice();
+            }
+        }
+
         let _ = mp3::snapshot_from_shared(shared_ptr, &mut samples);
 
-        let analyzer = unsafe { &mut *analyzer_ptr };
-        let m = analyzer.analyze(&samples, 1.0 / 60.0);
+        // smoothing runs on real time, not on how fast this loop spins
+        let now_us = unsafe { sys::sceKernelGetSystemTimeWide() };
+        let dt = (now_us - last_us) as f32 / 1_000_000.0;
+        last_us = now_us;
+
+        let m = analyzer.analyze(&samples, dt);
         let display_m = if m > SPECTRUM_SIZE { SPECTRUM_SIZE } else { m };
 
         unsafe {
             for i in 0..display_m {
                 SPECTRUM.0[i] = analyzer.out_smooth[i];
+                SMEAR.0[i] = analyzer.out_smear[i];
             }
             for i in display_m..SPECTRUM_SIZE {
                 SPECTRUM.0[i] = 0.0;
+                SMEAR.0[i] = 0.0;
             }
         }
         SPECTRUM_GEN.fetch_add(1, Ordering::Release);
@@ -320,6 +999,7 @@ extern "C" fn fft_thread_main(_args: usize, argp: *mut c_void) -> i32 {
     }
 
     unsafe { drop(Box::from_raw(analyzer_ptr)) };
+    take_analyzer_settings();
 
     0
 }
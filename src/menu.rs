@@ -0,0 +1,100 @@
+// settings menu, START opens/closes it, D-pad navigates
+
+use core::fmt;
+
+use psp::sys::CtrlButtons;
+
+use crate::input::Pad;
+use crate::overlay::{self, LINE_HEIGHT};
+use crate::settings::Settings;
+
+const WHITE: u32 = 0xFFFFFFFF;
+const YELLOW: u32 = 0xFF40FFFF; // ABGR
+/// Rows that fit under the title, the list scrolls past that
+const VISIBLE_ITEMS: usize = 26;
+
+pub struct Menu {
+    pub open: bool,
+    cursor: usize,
+    /// first row on screen
+    top: usize,
+}
+
+struct Value<'a>(&'a Settings, usize);
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.write_value(self.1, f)
+    }
+}
+
+impl Menu {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            cursor: 0,
+            top: 0,
+        }
+    }
+
+    /// Handle input, returns true if a setting changed.
+    /// While the menu is open it owns the D-pad.
+    pub fn update(&mut self, pad: &Pad, settings: &mut Settings) -> bool {
+        if pad.pressed(CtrlButtons::START) {
+            self.open = !self.open;
+        }
+        if !self.open {
+            return false;
+        }
+
+        let n = Settings::ITEM_COUNT;
+        if pad.pressed(CtrlButtons::UP) {
+            self.cursor = (self.cursor + n - 1) % n;
+        }
+        if pad.pressed(CtrlButtons::DOWN) {
+            self.cursor = (self.cursor + 1) % n;
+        }
+        // keep the cursor on screen
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + VISIBLE_ITEMS {
+            self.top = self.cursor + 1 - VISIBLE_ITEMS;
+        }
+
+        let mut dir = 0;
+        if pad.pressed(CtrlButtons::LEFT) {
+            dir -= 1;
+        }
+        if pad.pressed(CtrlButtons::RIGHT) {
+            dir += 1;
+        }
+        if dir != 0 {
+            settings.adjust(self.cursor, dir);
+            return true;
+        }
+        false
+    }
+
+    pub fn draw(&self, x: i32, y: i32, settings: &Settings) {
+        if !self.open {
+            return;
+        }
+        overlay::print(x, y, WHITE, format_args!("settings"));
+        let end = (self.top + VISIBLE_ITEMS).min(Settings::ITEM_COUNT);
+        for (row, i) in (self.top..end).enumerate() {
+            let selected = i == self.cursor;
+            let color = if selected { YELLOW } else { WHITE };
+            overlay::print(
+                x,
+                y + (row as i32 + 1) * LINE_HEIGHT,
+                color,
+                format_args!(
+                    "{} {:<12} < {} >",
+                    if selected { ">" } else { " " },
+                    Settings::label(i),
+                    Value(settings, i)
+                ),
+            );
+        }
+    }
+}
@@ -0,0 +1,610 @@
+// software mixer: owns the SRC output and sums every playing voice into it
+// voices are pulled from the mixer thread one `OUT_FRAMES` buffer at a time,
+// the mixing math itself lives in `dsp::mix`
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicPtr, AtomicU32, Ordering};
+use core::{ffi::c_void, ptr};
+
+extern crate alloc;
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+
+use psp::sys::{self, AudioOutputFrequency};
+
+use crate::dsp::dynamics::MasterSettings;
+use crate::dsp::mix::{MixBus, pan_gains};
+use crate::error::Error;
+use crate::stats::{MixerCounters, MixerStats, now_us};
+
+/// Stereo frames per SRC output call
+pub const OUT_FRAMES: usize = 1152;
+
+/// The output always runs at 44.1kHz, sources at other rates go through `dsp::resample`
+pub const SAMPLING_RATE: u32 = 44100;
+
+/// Voices that can be waiting to be picked up by the mixer thread at once
+const MAX_PENDING: usize = 8;
+
+/// Stereo frames buffered between a stream's producer and the mixer (~93ms at 44.1kHz)
+const STREAM_FRAMES: usize = 4096;
+
+/// Something the mixer can pull interleaved stereo from, at the mixer's rate
+pub trait Source: Send {
+    /// Fill `out` with the next frames, returns how many were written.
+    /// A short read while not `is_over` is an underrun, the rest plays as silence.
+    fn read(&mut self, out: &mut [i16]) -> usize;
+
+    /// true once there's nothing left to play
+    fn is_over(&self) -> bool;
+}
+
+/// Plays an in-memory interleaved stereo buffer once, e.g. a UI sound
+pub struct Clip {
+    samples: Arc<[i16]>,
+    pos: usize,
+}
+
+impl Clip {
+    pub fn new(samples: Arc<[i16]>) -> Self {
+        Self { samples, pos: 0 }
+    }
+}
+
+impl Source for Clip {
+    fn read(&mut self, out: &mut [i16]) -> usize {
+        let n = out.len().min(self.samples.len() - self.pos) & !1;
+        out[..n].copy_from_slice(&self.samples[self.pos..self.pos + n]);
+        self.pos += n;
+        n / 2
+    }
+
+    fn is_over(&self) -> bool {
+        self.samples.len() - self.pos < 2
+    }
+}
+
+struct VoiceControl {
+    /// linear, f32 bits
+    gain: AtomicU32,
+    /// -1..=1, f32 bits
+    pan: AtomicU32,
+    stop: AtomicBool,
+    /// faded out over one buffer, then the source isn't read until cleared
+    paused: AtomicBool,
+    done: AtomicBool,
+}
+
+impl VoiceControl {
+    fn new(gain: f32, pan: f32) -> Self {
+        Self {
+            gain: AtomicU32::new(gain.to_bits()),
+            pan: AtomicU32::new(pan.to_bits()),
+            stop: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            done: AtomicBool::new(false),
+        }
+    }
+
+    /// (left, right) gain the voice should be mixed at
+    fn gains(&self) -> (f32, f32) {
+        let gain = f32::from_bits(self.gain.load(Ordering::Relaxed));
+        let (l, r) = pan_gains(f32::from_bits(self.pan.load(Ordering::Relaxed)));
+        (l * gain, r * gain)
+    }
+}
+
+/// Handle to a playing voice. Dropping it doesn't stop the voice.
+#[derive(Clone)]
+pub struct Voice(Arc<VoiceControl>);
+
+impl Voice {
+    /// Linear gain, 1.0 = unchanged
+    #[allow(dead_code)]
+    pub fn set_gain(&self, gain: f32) {
+        self.0
+            .gain
+            .store(gain.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// -1 = hard left, 0 = center, 1 = hard right
+    #[allow(dead_code)]
+    pub fn set_pan(&self, pan: f32) {
+        self.0
+            .pan
+            .store(pan.clamp(-1.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Stop pulling from the voice (after a one buffer fade) until unpaused.
+    /// A streamed voice's writer blocks meanwhile, nothing is lost.
+    pub fn set_paused(&self, paused: bool) {
+        self.0.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.paused.load(Ordering::Relaxed)
+    }
+
+    /// Remove the voice at the next buffer
+    pub fn stop(&self) {
+        self.0.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// true once the mixer has dropped the voice
+    #[allow(dead_code)]
+    pub fn is_done(&self) -> bool {
+        self.0.done.load(Ordering::Relaxed)
+    }
+}
+
+/// Single producer, single consumer ring of interleaved stereo
+struct Ring {
+    buf: UnsafeCell<Box<[i16]>>,
+    /// positions in samples, wrapping, the capacity is a power of two
+    read: AtomicU32,
+    write: AtomicU32,
+    closed: AtomicBool,
+}
+
+// one side only touches the free part and the other only the filled part
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    fn new(frames: usize) -> Self {
+        let len = (frames * 2).next_power_of_two();
+        Self {
+            buf: UnsafeCell::new(vec![0i16; len].into_boxed_slice()),
+            read: AtomicU32::new(0),
+            write: AtomicU32::new(0),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        unsafe { (&*self.buf.get()).len() }
+    }
+
+    fn filled(&self) -> usize {
+        let w = self.write.load(Ordering::Acquire);
+        let r = self.read.load(Ordering::Acquire);
+        w.wrapping_sub(r) as usize
+    }
+}
+
+/// Producer side of a streamed voice, for sources that push (e.g. a decoder thread)
+pub struct StreamWriter {
+    ring: Arc<Ring>,
+    control: Arc<VoiceControl>,
+}
+
+impl StreamWriter {
+    /// Append interleaved stereo, blocking while the ring is full.
+    /// Returns false if the voice was stopped or the mixer went away.
+    pub fn write(&mut self, mut samples: &[i16]) -> bool {
+        let cap = self.ring.capacity();
+        let buf = self.ring.buf.get();
+        while !samples.is_empty() {
+            if self.control.stop.load(Ordering::Relaxed)
+                || self.control.done.load(Ordering::Relaxed)
+            {
+                return false;
+            }
+            let room = cap - self.ring.filled();
+            if room < 2 {
+                unsafe { sys::sceKernelDelayThreadCB(2000) };
+                continue;
+            }
+            let n = room.min(samples.len()) & !1;
+            let w = self.ring.write.load(Ordering::Relaxed);
+            for (i, &s) in samples[..n].iter().enumerate() {
+                let idx = w.wrapping_add(i as u32) as usize & (cap - 1);
+                unsafe { (&mut *buf)[idx] = s };
+            }
+            self.ring
+                .write
+                .store(w.wrapping_add(n as u32), Ordering::Release);
+            samples = &samples[n..];
+        }
+        true
+    }
+
+    /// Frames written that the mixer hasn't pulled yet
+    pub fn queued_frames(&self) -> usize {
+        self.ring.filled() / 2
+    }
+}
+
+impl Drop for StreamWriter {
+    fn drop(&mut self) {
+        self.ring.closed.store(true, Ordering::Release);
+    }
+}
+
+/// Consumer side of a streamed voice, lives in the mixer thread
+struct StreamSource {
+    ring: Arc<Ring>,
+}
+
+impl Source for StreamSource {
+    fn read(&mut self, out: &mut [i16]) -> usize {
+        let cap = self.ring.capacity();
+        let buf = self.ring.buf.get();
+        let n = self.ring.filled().min(out.len()) & !1;
+        let r = self.ring.read.load(Ordering::Relaxed);
+        for (i, o) in out[..n].iter_mut().enumerate() {
+            let idx = r.wrapping_add(i as u32) as usize & (cap - 1);
+            *o = unsafe { (&*buf)[idx] };
+        }
+        self.ring
+            .read
+            .store(r.wrapping_add(n as u32), Ordering::Release);
+        n / 2
+    }
+
+    fn is_over(&self) -> bool {
+        self.ring.closed.load(Ordering::Acquire) && self.ring.filled() == 0
+    }
+}
+
+/// A voice on its way to the mixer thread
+struct NewVoice {
+    source: Box<dyn Source>,
+    control: Arc<VoiceControl>,
+}
+
+/// A voice the mixer thread is playing
+struct Playing {
+    source: Box<dyn Source>,
+    control: Arc<VoiceControl>,
+    /// (left, right) gains the last buffer ended at
+    gains: (f32, f32),
+}
+
+/// The SRC output channel, always stereo
+struct Output {
+    /// when the last output call returned, for late buffer detection
+    last_output_us: u32,
+    /// playback length of the last buffer
+    last_buffer_us: u32,
+}
+
+impl Output {
+    fn reserve() -> Result<Self, Error> {
+        let _ = unsafe { sys::sceAudioSRCChRelease() };
+
+        let channel = unsafe {
+            sys::sceAudioSRCChReserve(OUT_FRAMES as i32, AudioOutputFrequency::Khz44_1, 2)
+        };
+        if channel < 0 {
+            return Err(Error::Audio(channel));
+        }
+
+        Ok(Self {
+            last_output_us: 0,
+            last_buffer_us: 0,
+        })
+    }
+
+    /// Queue one buffer of `OUT_FRAMES` stereo frames, blocks while the hardware is busy
+    fn write(&mut self, buf: &[i16], stats: &MixerCounters) -> Result<(), Error> {
+        // if producing this buffer took longer than the previous one lasts, the SRC queue ran dry
+        let start = now_us();
+        if self.last_output_us != 0 && start.wrapping_sub(self.last_output_us) > self.last_buffer_us
+        {
+            stats.late_buffers.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let result = unsafe { sys::sceAudioSRCOutputBlocking(0x8000, buf.as_ptr() as *mut c_void) };
+
+        let end = now_us();
+        stats.output.record(end.wrapping_sub(start));
+        self.last_output_us = end;
+        self.last_buffer_us = (buf.len() as u64 / 2 * 1_000_000 / SAMPLING_RATE as u64) as u32;
+
+        if result < 0 {
+            return Err(Error::Audio(result));
+        }
+
+        stats.buffers.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        unsafe {
+            for _ in 0..10 {
+                if sys::sceAudioSRCChRelease() >= 0 {
+                    break;
+                }
+                sys::sceKernelDelayThreadCB(100);
+            }
+        }
+    }
+}
+
+/// Shared state between the mixer thread and everyone adding voices
+struct MixerShared {
+    stop_requested: AtomicBool,
+    running: AtomicBool,
+    /// SCE error code that stopped the mixer thread, 0 if none
+    last_error: AtomicI32,
+    pending: [AtomicPtr<NewVoice>; MAX_PENDING],
+    limiter: AtomicBool,
+    /// f32 bits
+    threshold_db: AtomicU32,
+    /// f32 bits
+    release_ms: AtomicU32,
+    soft_clip: AtomicBool,
+    stats: MixerCounters,
+}
+
+impl MixerShared {
+    fn new() -> Self {
+        let shared = Self {
+            stop_requested: AtomicBool::new(false),
+            running: AtomicBool::new(true),
+            last_error: AtomicI32::new(0),
+            pending: [const { AtomicPtr::new(ptr::null_mut()) }; MAX_PENDING],
+            limiter: AtomicBool::new(true),
+            threshold_db: AtomicU32::new(0),
+            release_ms: AtomicU32::new(0),
+            soft_clip: AtomicBool::new(false),
+            stats: MixerCounters::default(),
+        };
+        shared.set_master(&MasterSettings::default());
+        shared
+    }
+
+    fn set_master(&self, settings: &MasterSettings) {
+        self.limiter.store(settings.limiter, Ordering::Relaxed);
+        self.threshold_db
+            .store(settings.threshold_db.to_bits(), Ordering::Relaxed);
+        self.release_ms
+            .store(settings.release_ms.to_bits(), Ordering::Relaxed);
+        self.soft_clip.store(settings.soft_clip, Ordering::Relaxed);
+    }
+
+    fn master(&self) -> MasterSettings {
+        MasterSettings {
+            limiter: self.limiter.load(Ordering::Relaxed),
+            threshold_db: f32::from_bits(self.threshold_db.load(Ordering::Relaxed)),
+            release_ms: f32::from_bits(self.release_ms.load(Ordering::Relaxed)),
+            soft_clip: self.soft_clip.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Hand a voice to the mixer thread, gives it back if every slot is taken
+    fn push(&self, voice: NewVoice) -> Result<(), NewVoice> {
+        let p = Box::into_raw(Box::new(voice));
+        for slot in self.pending.iter() {
+            if slot
+                .compare_exchange(ptr::null_mut(), p, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+        Err(*unsafe { Box::from_raw(p) })
+    }
+
+    fn take_pending(&self) -> impl Iterator<Item = NewVoice> + '_ {
+        self.pending.iter().filter_map(|slot| {
+            let p = slot.swap(ptr::null_mut(), Ordering::AcqRel);
+            (!p.is_null()).then(|| *unsafe { Box::from_raw(p) })
+        })
+    }
+}
+
+impl Drop for MixerShared {
+    fn drop(&mut self) {
+        self.take_pending()
+            .for_each(|v| v.control.done.store(true, Ordering::Relaxed));
+    }
+}
+
+/// Arguments passed to the mixer thread
+struct ThreadArgs {
+    shared: Arc<MixerShared>,
+}
+
+/// Mixer thread entry point
+extern "C" fn mixer_thread_main(_args: usize, argp: *mut c_void) -> i32 {
+    let args_ptr = unsafe { *(argp as *const *mut ThreadArgs) };
+    let args: Box<ThreadArgs> = unsafe { Box::from_raw(args_ptr) };
+    let shared = args.shared;
+
+    if let Err(Error::Audio(code)) = mixer_thread_inner(&shared) {
+        shared.last_error.store(code, Ordering::Relaxed);
+    }
+
+    // nothing will read these voices any more, let their producers give up
+    shared
+        .take_pending()
+        .for_each(|v| v.control.done.store(true, Ordering::Relaxed));
+    shared.running.store(false, Ordering::Relaxed);
+
+    drop(shared);
+    unsafe {
+        sys::sceKernelExitDeleteThread(0);
+    }
+
+    0
+}
+
+fn mixer_thread_inner(shared: &MixerShared) -> Result<(), Error> {
+    let stats = &shared.stats;
+
+    let mut output = Output::reserve()?;
+    let mut bus = MixBus::new(OUT_FRAMES, SAMPLING_RATE);
+
+    let mut voices: Vec<Playing> = Vec::new();
+    let mut scratch = vec![0i16; OUT_FRAMES * 2];
+    let mut out = vec![0i16; OUT_FRAMES * 2];
+
+    let result = loop {
+        if shared.stop_requested.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+
+        voices.extend(shared.take_pending().map(|v| Playing {
+            gains: v.control.gains(),
+            source: v.source,
+            control: v.control,
+        }));
+
+        bus.clear();
+        voices.retain_mut(|v| {
+            if v.control.stop.load(Ordering::Relaxed) {
+                v.control.done.store(true, Ordering::Relaxed);
+                return false;
+            }
+
+            // fade out over one buffer when pausing, then leave the source alone
+            let paused = v.control.paused.load(Ordering::Relaxed);
+            if paused && v.gains == (0.0, 0.0) {
+                return true;
+            }
+
+            let frames = v.source.read(&mut scratch);
+            let over = v.source.is_over();
+            if frames < OUT_FRAMES && !over {
+                stats.underruns.fetch_add(1, Ordering::Relaxed);
+            }
+            scratch[frames * 2..].fill(0);
+
+            let target = if paused {
+                (0.0, 0.0)
+            } else {
+                v.control.gains()
+            };
+            bus.add(&scratch, v.gains, target);
+            v.gains = target;
+
+            if over {
+                v.control.done.store(true, Ordering::Relaxed);
+            }
+            !over
+        });
+        stats.voices.store(voices.len() as u32, Ordering::Relaxed);
+
+        let reduction_db = bus.finish(&mut out, &shared.master());
+        stats
+            .gain_reduction
+            .store(reduction_db.to_bits(), Ordering::Relaxed);
+
+        if let Err(e) = output.write(&out, stats) {
+            break Err(e);
+        }
+    };
+
+    for v in voices.iter() {
+        v.control.done.store(true, Ordering::Relaxed);
+    }
+    result
+}
+
+/// Owns the audio output, everything that makes sound goes through here as a voice
+pub struct Mixer {
+    thid: sys::SceUid,
+    shared: Arc<MixerShared>,
+}
+
+impl Mixer {
+    pub fn new() -> Result<Self, Error> {
+        let shared = Arc::new(MixerShared::new());
+
+        let args = Box::new(ThreadArgs {
+            shared: shared.clone(),
+        });
+        let args_ptr = Box::into_raw(args);
+
+        let thid = unsafe {
+            sys::sceKernelCreateThread(
+                c"mixer_thread".as_ptr().cast(),
+                mixer_thread_main,
+                0x1E,   // just above the decoder threads it pulls from
+                0x2000, // 8KB stack
+                sys::ThreadAttributes::USER | sys::ThreadAttributes::VFPU,
+                ptr::null_mut(),
+            )
+        };
+
+        if thid.0 < 0 {
+            unsafe { drop(Box::from_raw(args_ptr)) };
+            return Err(Error::Thread(thid.0));
+        }
+
+        let result = unsafe {
+            sys::sceKernelStartThread(
+                thid,
+                core::mem::size_of::<*mut ThreadArgs>(),
+                &args_ptr as *const _ as *mut c_void,
+            )
+        };
+
+        if result < 0 {
+            unsafe {
+                let _ = sys::sceKernelDeleteThread(thid);
+                drop(Box::from_raw(args_ptr));
+            }
+            return Err(Error::Thread(result));
+        }
+
+        Ok(Self { thid, shared })
+    }
+
+    /// Start playing `source`. Returns None if too many voices are waiting to start.
+    pub fn play(&self, source: Box<dyn Source>, gain: f32, pan: f32) -> Option<Voice> {
+        let control = Arc::new(VoiceControl::new(gain, pan));
+        let voice = NewVoice {
+            source,
+            control: control.clone(),
+        };
+        self.shared.push(voice).ok()?;
+        Some(Voice(control))
+    }
+
+    /// Add a voice fed from another thread through the returned writer
+    pub fn stream(&self, gain: f32, pan: f32) -> Option<(StreamWriter, Voice)> {
+        let ring = Arc::new(Ring::new(STREAM_FRAMES));
+        let source = StreamSource { ring: ring.clone() };
+        let voice = self.play(Box::new(source), gain, pan)?;
+        let writer = StreamWriter {
+            ring,
+            control: voice.0.clone(),
+        };
+        Some((writer, voice))
+    }
+
+    /// DC blocker, limiter and soft clip settings for the master bus
+    pub fn set_master(&self, settings: &MasterSettings) {
+        self.shared.set_master(settings);
+    }
+
+    /// Err with the output error if the mixer thread has stopped
+    pub fn tick(&self) -> Result<(), Error> {
+        if self.shared.running.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        match self.shared.last_error.load(Ordering::Relaxed) {
+            0 => Ok(()),
+            code => Err(Error::Audio(code)),
+        }
+    }
+
+    /// mixer thread timings and counters for the debug overlay
+    pub fn stats(&self) -> MixerStats {
+        self.shared.stats.snapshot()
+    }
+}
+
+impl Drop for Mixer {
+    fn drop(&mut self) {
+        self.shared.stop_requested.store(true, Ordering::Relaxed);
+
+        unsafe {
+            let _ = sys::sceKernelWaitThreadEnd(self.thid, ptr::null_mut());
+            let _ = sys::sceKernelDeleteThread(self.thid);
+        }
+    }
+}
@@ -1,220 +1,279 @@
-use crate::fft::FFT_SIZE;
+use crate::decoder::Decoder;
+use crate::dsp::ab_loop::AbLoop;
+use crate::dsp::crossfade::{Crossfade, MAX_CROSSFADE_SECS};
+use crate::dsp::dynamics::{Master, MasterSettings};
+use crate::dsp::eq::{Eq, EqDesign};
+use crate::dsp::gain::{SmoothGain, gain_to_db, to_i16, volume_to_gain};
+use crate::dsp::karaoke::{Karaoke, KaraokeMode};
+use crate::dsp::loudness::{Loudness, LoudnessMeter};
+use crate::dsp::meter::{Ballistics, ChannelLevel, LevelMeter};
+use crate::dsp::replaygain::ReplayGainMode;
+use crate::dsp::resample::{ResampleMode, Resampler};
+use crate::dsp::stretch::{MAX_SEMITONES, MAX_SPEED, MIN_SPEED, Stretch};
+use crate::error::{Error, SCE_ERROR_BUSY, SCE_ERROR_MODULE_ALREADY_LOADED};
+use crate::fft::MAX_FFT_SIZE;
+use crate::io::{ByteStream, SliceReader};
+use crate::mixer::{Mixer, OUT_FRAMES, SAMPLING_RATE, StreamWriter, Voice};
+use crate::readahead::{Readahead, ReadaheadCounters};
+use crate::stats::{PlayerCounters, PlayerStats};
 use crate::utils::AssetStream;
-use core::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicPtr, AtomicU8, AtomicU32, Ordering};
 use core::{ffi::c_void, ptr};
-use psp::sys::{
-    self, AudioOutputFrequency, Mp3Handle, sceMp3CheckStreamDataNeeded, sceMp3Decode,
-    sceMp3GetInfoToAddStreamData, sceMp3Init, sceMp3InitResource, sceMp3NotifyAddStreamData,
-    sceMp3ReleaseMp3Handle, sceMp3ReserveMp3Handle, sceMp3SetLoopNum, sceMp3TermResource,
-};
+use psp::sys::{self, sceMp3InitResource, sceMp3TermResource};
 
 // TODO: make it loop
 
 extern crate alloc;
-use alloc::{boxed::Box, string::String};
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
 
 #[repr(C, align(64))]
 struct Align64<T>(T);
 
-const MP3_BUF_SIZE: usize = 16 * 1024; // 16KB for MP3 stream data
-const PCM_BUF_SIZE: usize = 16 * (1152 / 2); // PCM output buffer
+/// Decoders sharing the sceMp3 resource, only changed with the lock held
+static MP3_RESOURCE_USERS: AtomicU32 = AtomicU32::new(0);
+/// Held across the count change and the init/term that goes with it, so
+/// nobody reserves a handle while the resource is still coming up
+static MP3_RESOURCE_LOCK: AtomicBool = AtomicBool::new(false);
+/// interleaved stereo, enough for the largest analyzer FFT
+const PCM_RING_SIZE: usize = 2 * MAX_FFT_SIZE;
+static mut PCM_RING: Align64<[i16; PCM_RING_SIZE]> = Align64([0; PCM_RING_SIZE]);
 
-static mut MP3_BUF: Align64<[u8; MP3_BUF_SIZE]> = Align64([0; MP3_BUF_SIZE]);
-static mut PCM_BUF: Align64<[u8; PCM_BUF_SIZE]> = Align64([0; PCM_BUF_SIZE]);
-static mut PCM_RING: Align64<[i16; FFT_SIZE]> = Align64([0; FFT_SIZE]);
+type BoxedStream = Box<dyn ByteStream + Send>;
 
-/// Find the start of the actual MP3 stream by skipping metadata tags (ID3v2, APE)
-/// Returns the byte offset where the MP3 audio data begins
-fn find_stream_start(stream: &mut AssetStream) -> Result<u32, i32> {
-    let mut header = [0u8; 32];
+/// Loop point that isn't set
+const NO_POINT: u32 = u32::MAX;
 
-    stream.seek(0, sys::IoWhence::Set)?;
-
-    let n = stream.read(&mut header)?;
-    if n < 10 {
-        return Ok(0);
-    }
-
-    if header[0] == b'I' && header[1] == b'D' && header[2] == b'3' {
-        let size = ((header[6] as u32 & 0x7F) << 21)
-            | ((header[7] as u32 & 0x7F) << 14)
-            | ((header[8] as u32 & 0x7F) << 7)
-            | (header[9] as u32 & 0x7F);
-        return Ok(size + 10);
-    }
+fn frames_to_ms(frames: u32) -> u32 {
+    (frames as u64 * 1000 / SAMPLING_RATE as u64) as u32
+}
 
-    if header[0] == b'A' && header[1] == b'P' && header[2] == b'E' && header[3] == b'T' {
-        let size = (header[12] as u32)
-            | ((header[13] as u32) << 8)
-            | ((header[14] as u32) << 16)
-            | ((header[15] as u32) << 24);
-        return Ok(size + 32);
+/// Analyzer tap: the PCM ring, fed with what's about to be heard
+fn tap(shared: &SharedState, samples: &[i16]) {
+    // push samples into PCM ring buffer for analyzer
+    let write_base = shared
+        .pcm_write
+        .fetch_add(samples.len() as i32, Ordering::Relaxed) as isize;
+    for (i, &s) in samples.iter().enumerate() {
+        let idx = (write_base + i as isize).rem_euclid(PCM_RING_SIZE as isize);
+        unsafe {
+            PCM_RING.0[idx as usize] = s;
+        }
     }
-
-    Ok(0)
 }
 
-/// Fill the MP3 stream buffer from the file
-/// Returns true if there's more data, false if we hit the start of file
-fn fill_stream_buffer(fd: &mut AssetStream, handle: Mp3Handle) -> Result<bool, i32> {
-    let mut dst: *mut u8 = ptr::null_mut();
-    let mut to_write: i32 = 0;
-    let mut src_pos: i32 = 0;
+/// Shared state between main thread and audio thread
+struct SharedState {
+    stop_requested: AtomicBool,
+    finished: AtomicBool,
+    error: AtomicBool,
+    last_error: AtomicI32,
+    last_error_kind: AtomicU8,
+    /// per channel peak, RMS and hold in dBFS as f32 bits
+    meter: [[AtomicU32; 3]; 2],
+    clip: [AtomicBool; 2],
+    ballistics: AtomicU8,
+    /// momentary, short-term, integrated, range and true peak as f32 bits
+    loudness: [AtomicU32; 5],
+    loudness_reset: AtomicBool,
+    pcm_write: AtomicI32,
+    /// 0..=1 slider position as f32 bits
+    volume: AtomicU32,
+    muted: AtomicBool,
+    replaygain_mode: AtomicU8,
+    /// gain currently applied by ReplayGain, dB as f32 bits
+    replaygain_db: AtomicU32,
+    resample_mode: AtomicU8,
+    karaoke: AtomicU8,
+    /// limiter and soft clip at the end of the track chain, as on the mixer
+    limiter: AtomicBool,
+    /// f32 bits
+    threshold_db: AtomicU32,
+    /// f32 bits
+    release_ms: AtomicU32,
+    soft_clip: AtomicBool,
+    /// next track, taken by the audio thread when the current one nears its end
+    queued: AtomicPtr<BoxedStream>,
+    /// new EQ settings, picked up by the audio thread at the next buffer
+    eq: AtomicPtr<EqDesign>,
+    crossfade_ms: AtomicU32,
+    /// bumped every time playback moves on to a queued track
+    track: AtomicU32,
+    /// playback speed, f32 bits
+    speed: AtomicU32,
+    /// pitch shift in semitones
+    pitch: AtomicI32,
+    /// where the current track is, in source time
+    position_ms: AtomicU32,
+    /// length of the current track, 0 if unknown
+    duration_ms: AtomicU32,
+    /// set A / set B / clear the A-B loop, taken by the audio thread
+    loop_mark: AtomicBool,
+    loop_clear: AtomicBool,
+    /// loop points in ms, `NO_POINT` when not set
+    loop_a_ms: AtomicU32,
+    loop_b_ms: AtomicU32,
+    stats: PlayerCounters,
+}
 
-    let status =
-        unsafe { sceMp3GetInfoToAddStreamData(handle, &mut dst, &mut to_write, &mut src_pos) };
-    if status < 0 {
-        return Err(status);
+impl SharedState {
+    fn new() -> Self {
+        let master = MasterSettings::default();
+        Self {
+            stop_requested: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            error: AtomicBool::new(false),
+            last_error: AtomicI32::new(0),
+            last_error_kind: AtomicU8::new(0),
+            meter: [const { [const { AtomicU32::new(0) }; 3] }; 2],
+            clip: [const { AtomicBool::new(false) }; 2],
+            ballistics: AtomicU8::new(Ballistics::default().to_u8()),
+            loudness: [const { AtomicU32::new(0) }; 5],
+            loudness_reset: AtomicBool::new(false),
+            pcm_write: AtomicI32::new(0),
+            volume: AtomicU32::new(1.0f32.to_bits()),
+            muted: AtomicBool::new(false),
+            replaygain_mode: AtomicU8::new(ReplayGainMode::Off.to_u8()),
+            replaygain_db: AtomicU32::new(0.0f32.to_bits()),
+            resample_mode: AtomicU8::new(ResampleMode::default().to_u8()),
+            karaoke: AtomicU8::new(KaraokeMode::Off.to_u8()),
+            limiter: AtomicBool::new(master.limiter),
+            threshold_db: AtomicU32::new(master.threshold_db.to_bits()),
+            release_ms: AtomicU32::new(master.release_ms.to_bits()),
+            soft_clip: AtomicBool::new(master.soft_clip),
+            queued: AtomicPtr::new(ptr::null_mut()),
+            eq: AtomicPtr::new(ptr::null_mut()),
+            crossfade_ms: AtomicU32::new(0),
+            track: AtomicU32::new(0),
+            speed: AtomicU32::new(1.0f32.to_bits()),
+            pitch: AtomicI32::new(0),
+            position_ms: AtomicU32::new(0),
+            duration_ms: AtomicU32::new(0),
+            loop_mark: AtomicBool::new(false),
+            loop_clear: AtomicBool::new(false),
+            loop_a_ms: AtomicU32::new(NO_POINT),
+            loop_b_ms: AtomicU32::new(NO_POINT),
+            stats: PlayerCounters::default(),
+        }
     }
 
-    let seek_result = fd.seek(src_pos as i64, sys::IoWhence::Set);
-    if seek_result.is_err() {
-        return Err(seek_result.unwrap_err());
+    fn set_error(&self, err: Error) {
+        let (kind, code) = err.to_parts();
+        self.last_error_kind.store(kind, Ordering::Relaxed);
+        self.last_error.store(code, Ordering::Relaxed);
+        self.error.store(true, Ordering::Relaxed);
+        self.finished.store(true, Ordering::Relaxed);
     }
 
-    let buf = unsafe { core::slice::from_raw_parts_mut(dst, to_write as usize) };
-    let read = fd.read(buf)?;
-
-    if read == 0 {
-        // EOF reached
-        let _ = unsafe { sceMp3NotifyAddStreamData(handle, 0) };
-        return Ok(false);
+    fn last_error(&self) -> Error {
+        Error::from_parts(
+            self.last_error_kind.load(Ordering::Relaxed),
+            self.last_error.load(Ordering::Relaxed),
+        )
     }
 
-    let status = unsafe { sceMp3NotifyAddStreamData(handle, read as i32) };
-    if status < 0 {
-        return Err(status);
+    fn target_gain(&self) -> f32 {
+        if self.muted.load(Ordering::Relaxed) {
+            return 0.0;
+        }
+        volume_to_gain(f32::from_bits(self.volume.load(Ordering::Relaxed)))
     }
 
-    Ok(src_pos > 0)
-}
-
-/// Feed the MP3 decoder if it needs more data
-fn mp3_feed(instance: &mut Mp3Instance) -> Result<bool, i32> {
-    if instance.error || instance.paused {
-        return Ok(false);
+    fn master(&self) -> MasterSettings {
+        MasterSettings {
+            limiter: self.limiter.load(Ordering::Relaxed),
+            threshold_db: f32::from_bits(self.threshold_db.load(Ordering::Relaxed)),
+            release_ms: f32::from_bits(self.release_ms.load(Ordering::Relaxed)),
+            soft_clip: self.soft_clip.load(Ordering::Relaxed),
+        }
     }
 
-    let needed = unsafe { sceMp3CheckStreamDataNeeded(instance.handle) };
-    if needed > 0 {
-        fill_stream_buffer(&mut instance.stream, instance.handle)?;
+    fn set_levels(&self, levels: &[ChannelLevel; 2]) {
+        for ((m, clip), l) in self.meter.iter().zip(self.clip.iter()).zip(levels) {
+            m[0].store(l.peak_db.to_bits(), Ordering::Relaxed);
+            m[1].store(l.rms_db.to_bits(), Ordering::Relaxed);
+            m[2].store(l.hold_db.to_bits(), Ordering::Relaxed);
+            clip.store(l.clip, Ordering::Relaxed);
+        }
     }
 
-    let mut buf: *mut i16 = ptr::null_mut();
-    let bytes_decoded = unsafe { sceMp3Decode(instance.handle, &mut buf) };
-
-    if bytes_decoded < 0 {
-        if bytes_decoded as u32 != 0x80671402 {
-            instance.error = true;
-            return Err(bytes_decoded);
+    fn set_loudness(&self, l: &Loudness) {
+        let values = [
+            l.momentary,
+            l.short_term,
+            l.integrated,
+            l.range,
+            l.true_peak,
+        ];
+        for (a, v) in self.loudness.iter().zip(values) {
+            a.store(v.to_bits(), Ordering::Relaxed);
         }
     }
 
-    if bytes_decoded == 0 || bytes_decoded as u32 == 0x80671402 {
-        instance.over = true;
-        instance.paused = true;
-        let _ = unsafe { sys::sceMp3ResetPlayPosition(instance.handle) };
-        return Ok(true);
+    fn loudness(&self) -> Loudness {
+        let v = |i: usize| f32::from_bits(self.loudness[i].load(Ordering::Relaxed));
+        Loudness {
+            momentary: v(0),
+            short_term: v(1),
+            integrated: v(2),
+            range: v(3),
+            true_peak: v(4),
+        }
     }
 
-    // compute simple peak level from decoded PCM (i16 samples)
-    if !buf.is_null() && bytes_decoded > 0 {
-        let sample_count = (bytes_decoded as usize) / core::mem::size_of::<i16>();
-        if sample_count > 0 {
-            let samples = unsafe { core::slice::from_raw_parts(buf as *const i16, sample_count) };
-            let mut peak: i32 = 0;
-            for &s in samples.iter() {
-                let v = (s as i32).abs();
-                if v > peak {
-                    peak = v;
-                }
-            }
-            // normalize to 0..100
-            let lvl = (peak as i64 * 100 / i16::MAX as i64) as i32;
-            if !instance.shared.is_null() {
-                unsafe {
-                    (&*instance.shared).set_level(lvl);
-                }
-                // push samples into PCM ring buffer for analyzer
-                let write_base = unsafe {
-                    (&*instance.shared)
-                        .pcm_write
-                        .fetch_add(sample_count as i32, Ordering::Relaxed)
-                } as isize;
-                for (i, &s) in samples.iter().enumerate() {
-                    let idx = ((write_base + i as isize) % FFT_SIZE as isize + FFT_SIZE as isize)
-                        % FFT_SIZE as isize;
-                    unsafe {
-                        PCM_RING.0[idx as usize] = s;
-                    }
-                }
+    fn levels(&self) -> [ChannelLevel; 2] {
+        core::array::from_fn(|c| {
+            let db = |i: usize| f32::from_bits(self.meter[c][i].load(Ordering::Relaxed));
+            ChannelLevel {
+                peak_db: db(0),
+                rms_db: db(1),
+                hold_db: db(2),
+                clip: self.clip[c].load(Ordering::Relaxed),
             }
-        }
+        })
     }
 
-    let result = unsafe { sys::sceAudioSRCOutputBlocking(0x8000, buf as *mut c_void) };
-
-    if result < 0 {
-        instance.error = true;
-        return Err(result);
+    /// Replace the queued track, returns true if one was still waiting (and got dropped)
+    fn queue(&self, stream: BoxedStream) -> bool {
+        let new = Box::into_raw(Box::new(stream));
+        let old = self.queued.swap(new, Ordering::AcqRel);
+        if old.is_null() {
+            return false;
+        }
+        unsafe { drop(Box::from_raw(old)) };
+        true
     }
 
-    instance.num_played += result;
-
-    Ok(false)
-}
-
-/// Internal state for an MP3 playback instance
-#[allow(dead_code)]
-struct Mp3Instance {
-    stream: AssetStream,
-    handle: Mp3Handle,
-    paused: bool,
-    error: bool,
-    over: bool,
-    num_played: i32,
-    sampling_rate: i32,
-    num_channels: i32,
-    max_sample: i32,
-    shared: *mut SharedState,
-}
-
-/// Shared state between main thread and audio thread
-struct SharedState {
-    stop_requested: AtomicBool,
-    finished: AtomicBool,
-    error: AtomicBool,
-    last_error: AtomicI32,
-    level: AtomicI32,
-    pcm_write: AtomicI32,
-}
+    fn take_queued(&self) -> Option<BoxedStream> {
+        let p = self.queued.swap(ptr::null_mut(), Ordering::AcqRel);
+        (!p.is_null()).then(|| *unsafe { Box::from_raw(p) })
+    }
 
-impl SharedState {
-    fn new() -> Self {
-        Self {
-            stop_requested: AtomicBool::new(false),
-            finished: AtomicBool::new(false),
-            error: AtomicBool::new(false),
-            last_error: AtomicI32::new(0),
-            level: AtomicI32::new(0),
-            pcm_write: AtomicI32::new(0),
+    fn set_eq(&self, design: EqDesign) {
+        let old = self
+            .eq
+            .swap(Box::into_raw(Box::new(design)), Ordering::AcqRel);
+        if !old.is_null() {
+            unsafe { drop(Box::from_raw(old)) };
         }
     }
 
-    fn set_error(&self, err: i32) {
-        self.last_error.store(err, Ordering::Relaxed);
-        self.error.store(true, Ordering::Relaxed);
-        self.finished.store(true, Ordering::Relaxed);
+    fn take_eq(&self) -> Option<EqDesign> {
+        let p = self.eq.swap(ptr::null_mut(), Ordering::AcqRel);
+        (!p.is_null()).then(|| *unsafe { Box::from_raw(p) })
     }
+}
 
-    fn set_level(&self, v: i32) {
-        self.level.store(v, Ordering::Relaxed);
+impl Drop for SharedState {
+    fn drop(&mut self) {
+        drop(self.take_queued());
+        self.take_eq();
     }
 }
 
 /// Arguments passed to the audio thread
 struct ThreadArgs {
-    path: String,
+    stream: BoxedStream,
+    writer: StreamWriter,
     shared: *mut SharedState,
 }
 
@@ -226,9 +285,14 @@ extern "C" fn mp3_thread_main(_args: usize, argp: *mut c_void) -> i32 {
     // argp points to a copy of the pointer value that was passed to sceKernelStartThread
     let args_ptr = unsafe { *(argp as *const *mut ThreadArgs) };
     let args: Box<ThreadArgs> = unsafe { Box::from_raw(args_ptr) };
-    let shared = unsafe { &*args.shared };
+    let ThreadArgs {
+        stream,
+        writer,
+        shared,
+    } = *args;
+    let shared = unsafe { &*shared };
 
-    let result = mp3_thread_inner(&args.path, shared);
+    let result = mp3_thread_inner(stream, writer, shared);
 
     if let Err(e) = result {
         shared.set_error(e);
@@ -244,120 +308,326 @@ extern "C" fn mp3_thread_main(_args: usize, argp: *mut c_void) -> i32 {
 }
 
 /// Inner playback logic for the audio thread
-fn mp3_thread_inner(path: &str, shared: &SharedState) -> Result<(), i32> {
-    const SCE_ERROR_MODULE_ALREADY_LOADED: i32 = 0x80111102u32 as i32;
-
+fn mp3_thread_inner(
+    stream: BoxedStream,
+    writer: StreamWriter,
+    shared: &SharedState,
+) -> Result<(), Error> {
     unsafe {
         let r = sys::sceUtilityLoadModule(sys::Module::AvCodec);
         if r < 0 && r != SCE_ERROR_MODULE_ALREADY_LOADED {
-            return Err(r);
+            return Err(Error::Module(r));
         }
         let r = sys::sceUtilityLoadModule(sys::Module::AvMp3);
         if r < 0 && r != SCE_ERROR_MODULE_ALREADY_LOADED {
-            return Err(r);
+            return Err(Error::Module(r));
         }
     }
 
-    let mut stream = AssetStream::open(path)?;
+    // several players (music, previews) can be decoding at once
+    with_mp3_resource(|users| {
+        if users.load(Ordering::Relaxed) == 0 {
+            let init_result = unsafe { sceMp3InitResource() };
+            if init_result < 0 {
+                return Err(Error::Decoder(init_result));
+            }
+        }
+        users.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    })?;
 
-    let file_end = stream.size()?;
+    let result = play(stream, writer, shared);
 
-    let stream_start = find_stream_start(&mut stream)?;
+    with_mp3_resource(|users| {
+        if users.fetch_sub(1, Ordering::Relaxed) == 1 {
+            unsafe { sceMp3TermResource() };
+        }
+    });
 
-    let init_result = unsafe { sceMp3InitResource() };
-    if init_result < 0 {
-        return Err(init_result);
-    }
+    result
+}
 
-    let mp3_buf = unsafe { &raw mut MP3_BUF.0 as *mut u8 as *mut c_void };
-    let pcm_buf = unsafe { &raw mut PCM_BUF.0 as *mut u8 as *mut c_void };
+/// Run `f` on the resource count with the lock held. It spins with a sleep,
+/// the holder can be a lower priority thread that needs the cpu to finish
+fn with_mp3_resource<T>(f: impl FnOnce(&AtomicU32) -> T) -> T {
+    while MP3_RESOURCE_LOCK
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        unsafe { sys::sceKernelDelayThread(1000) };
+    }
+    let result = f(&MP3_RESOURCE_USERS);
+    MP3_RESOURCE_LOCK.store(false, Ordering::Release);
+    result
+}
 
-    let mut init_arg = sys::SceMp3InitArg {
-        mp3_stream_start: stream_start,
-        unk1: 0,
-        mp3_stream_end: file_end as u32,
-        unk2: 0,
-        mp3_buf,
-        mp3_buf_size: MP3_BUF_SIZE as i32,
-        pcm_buf,
-        pcm_buf_size: PCM_BUF_SIZE as i32,
-    };
+/// A decoder plus the resampler that brings it to the mixer's rate
+struct Track {
+    decoder: Decoder,
+    resampler: Resampler,
+    rg_gain: SmoothGain,
+    /// decoded frames at the track's own rate
+    decoded: Vec<i16>,
+    /// resampled frames, `ready_pos..` not handed out yet
+    ready: Vec<i16>,
+    ready_pos: usize,
+    /// frames handed out so far, at the mixer rate
+    played: u32,
+}
 
-    let handle_raw = unsafe { sceMp3ReserveMp3Handle(&mut init_arg) };
-    if handle_raw < 0 {
-        unsafe { sceMp3TermResource() };
-        return Err(handle_raw);
+impl Track {
+    fn new(stream: BoxedStream, mode: ResampleMode) -> Result<Self, Error> {
+        let decoder = Decoder::new(stream)?;
+        let resampler = Resampler::new(decoder.sampling_rate, SAMPLING_RATE, mode);
+        Ok(Self {
+            decoder,
+            resampler,
+            rg_gain: SmoothGain::new(1.0),
+            decoded: vec![0i16; OUT_FRAMES * 2],
+            ready: Vec::with_capacity(OUT_FRAMES * 2),
+            ready_pos: 0,
+            played: 0,
+        })
     }
-    let handle = Mp3Handle(handle_raw);
 
-    fill_stream_buffer(&mut stream, handle)?;
-
-    let init_status = unsafe { sceMp3Init(handle) };
-    if init_status < 0 {
-        unsafe {
-            sceMp3ReleaseMp3Handle(handle);
-            sceMp3TermResource();
-        }
-        return Err(init_status);
+    fn is_over(&self) -> bool {
+        self.decoder.is_over() && self.ready_pos >= self.ready.len()
     }
 
-    let _ = unsafe { sys::sceAudioSRCChRelease() };
-
-    let _ = unsafe { sceMp3SetLoopNum(handle, 0) };
+    /// Output frames left, if the decoder could estimate the length
+    fn remaining_frames(&self) -> Option<u32> {
+        let buffered = (self.ready.len() - self.ready_pos) as u32 / 2;
+        self.decoder
+            .remaining_frames()
+            .map(|r| self.resampler.output_frames(r) + buffered)
+    }
 
-    let sampling_rate = unsafe { sys::sceMp3GetSamplingRate(handle) };
-    let num_channels = unsafe { sys::sceMp3GetMp3ChannelNum(handle) };
-    let max_sample = unsafe { sys::sceMp3GetMaxOutputSample(handle) };
+    /// Fill `out` (interleaved stereo at the mixer rate), fewer frames only at the end
+    fn read(
+        &mut self,
+        out: &mut [i16],
+        resample_mode: ResampleMode,
+        stats: &PlayerCounters,
+    ) -> Result<usize, Error> {
+        self.resampler.mode = resample_mode;
+        let mut written = 0usize;
+        while written < out.len() {
+            if self.ready_pos >= self.ready.len() {
+                if self.decoder.is_over() {
+                    break;
+                }
+                let frames = self.decoder.read(&mut self.decoded, stats)?;
+                self.ready.clear();
+                self.ready_pos = 0;
+                self.resampler
+                    .process(&self.decoded[..frames * 2], &mut self.ready);
+                continue;
+            }
+            let n = (out.len() - written).min(self.ready.len() - self.ready_pos);
+            out[written..written + n]
+                .copy_from_slice(&self.ready[self.ready_pos..self.ready_pos + n]);
+            written += n;
+            self.ready_pos += n;
+        }
+        self.played += written as u32 / 2;
+        Ok(written / 2)
+    }
 
-    let freq: AudioOutputFrequency = unsafe { core::mem::transmute(sampling_rate) };
-    let channel = unsafe { sys::sceAudioSRCChReserve(max_sample, freq, num_channels) };
-    if channel < 0 {
-        unsafe {
-            sceMp3ReleaseMp3Handle(handle);
-            sceMp3TermResource();
+    /// `samples` into the track chain with this track's ReplayGain,
+    /// free to go over full scale until the limiter
+    fn replaygain(&mut self, samples: &[i16], mode: ReplayGainMode, out: &mut [f32]) {
+        for (o, &s) in out.iter_mut().zip(samples) {
+            *o = s as f32;
         }
-        return Err(channel);
+        let target = self.decoder.replaygain_gain(mode);
+        self.rg_gain.process_f32(target, out, 2);
     }
+}
 
-    let mut instance = Mp3Instance {
-        stream,
-        handle,
-        paused: false,
-        error: false,
-        over: false,
-        num_played: 0,
-        sampling_rate,
-        num_channels,
-        max_sample,
-        shared: shared as *const _ as *mut SharedState,
-    };
-
-    while !instance.over && !instance.error {
+/// Source frames between the next one into the chain and the speaker: the
+/// limiter's look-ahead, the stretcher's backlog, and the mixer ring plus the
+/// buffer the hardware is playing, at the current speed
+fn latency_frames(master: &Master, stretch: &Stretch, writer: &StreamWriter) -> u32 {
+    let queued = (writer.queued_frames() + OUT_FRAMES) as f32 * stretch.speed();
+    (master.latency_frames() + stretch.buffered_frames() + queued as usize) as u32
+}
+
+/// Play `stream` and then whatever gets queued after it, until the queue runs dry
+fn play(stream: BoxedStream, mut writer: StreamWriter, shared: &SharedState) -> Result<(), Error> {
+    let stats = &shared.stats;
+    let resample_mode = || ResampleMode::from_u8(shared.resample_mode.load(Ordering::Relaxed));
+
+    let mut current = Track::new(stream, resample_mode())?;
+
+    // the track fading in, decoded alongside `current`
+    let mut next: Option<Track> = None;
+    let mut fade: Option<Crossfade> = None;
+
+    let mut eq = Eq::new(SAMPLING_RATE);
+    let mut karaoke = Karaoke::new(SAMPLING_RATE);
+    let mut master = Master::new(SAMPLING_RATE);
+    let mut meter = LevelMeter::new(SAMPLING_RATE);
+    shared.set_levels(&meter.levels());
+    let mut loudness = LoudnessMeter::new(SAMPLING_RATE);
+    shared.set_loudness(&loudness.readings());
+    let mut stretch = Stretch::new(SAMPLING_RATE);
+    let mut ab = AbLoop::new(SAMPLING_RATE);
+    let mut gain = SmoothGain::new(shared.target_gain());
+    let mut buf = vec![0i16; OUT_FRAMES * 2];
+    let mut incoming = vec![0i16; OUT_FRAMES * 2];
+    // ReplayGain to the limiter runs in f32, so boosts have headroom until
+    // the one conversion back to i16
+    let mut chain = vec![0.0f32; OUT_FRAMES * 2];
+    let mut incoming_chain = vec![0.0f32; OUT_FRAMES * 2];
+    // what comes out of the stretch stage, longer than `buf` when slowed down
+    let mut out = Vec::with_capacity(OUT_FRAMES * 2 * 3);
+
+    loop {
         if shared.stop_requested.load(Ordering::Relaxed) {
             break;
         }
 
-        unsafe { sys::sceKernelDelayThreadCB(5000) };
+        if current.is_over() && !ab.is_looping() {
+            let incoming_track = match next.take() {
+                Some(t) => t,
+                None => match shared.take_queued() {
+                    Some(s) => Track::new(s, resample_mode())?,
+                    None => {
+                        // let the stretcher's last few ms out
+                        out.clear();
+                        stretch.flush(&mut out);
+                        gain.process(shared.target_gain(), &mut out, 2);
+                        writer.write(&out);
+                        break;
+                    }
+                },
+            };
+            fade = None;
+            current = incoming_track;
+            ab.clear();
+            shared.track.fetch_add(1, Ordering::Relaxed);
+        }
 
-        match mp3_feed(&mut instance) {
-            Ok(_) => {}
-            Err(e) => {
-                shared.set_error(e);
-                break;
-            }
+        let rg_mode = ReplayGainMode::from_u8(shared.replaygain_mode.load(Ordering::Relaxed));
+        let rs_mode = resample_mode();
+        shared.replaygain_db.store(
+            gain_to_db(current.decoder.replaygain_gain(rg_mode)).to_bits(),
+            Ordering::Relaxed,
+        );
+
+        // marks land on what's being heard, not on where the decoder is
+        if shared.loop_clear.swap(false, Ordering::Relaxed) {
+            ab.clear();
+        }
+        if shared.loop_mark.swap(false, Ordering::Relaxed) {
+            ab.mark(current.played, latency_frames(&master, &stretch, &writer));
         }
-    }
 
-    unsafe {
-        for _ in 0..10 {
-            if sys::sceAudioSRCChRelease() >= 0 {
-                break;
+        if ab.is_looping() {
+            // the decoder waits until the loop is cleared
+            ab.read(&mut buf);
+        } else {
+            let frames = current.read(&mut buf, rs_mode, stats)?;
+            buf[frames * 2..].fill(0);
+            ab.record(&buf[..frames * 2]);
+        }
+        current.replaygain(&buf, rg_mode, &mut chain);
+        let (a, b) = ab.points().unwrap_or((NO_POINT, None));
+        shared.loop_a_ms.store(
+            if a == NO_POINT { a } else { frames_to_ms(a) },
+            Ordering::Relaxed,
+        );
+        shared
+            .loop_b_ms
+            .store(b.map_or(NO_POINT, frames_to_ms), Ordering::Relaxed);
+
+        // start decoding the next track once we're inside the fade window
+        let fade_frames = (shared.crossfade_ms.load(Ordering::Relaxed) as u64
+            * SAMPLING_RATE as u64
+            / 1000) as u32;
+        if next.is_none()
+            && !ab.is_looping()
+            && fade_frames > 0
+            && let Some(remaining) = current.remaining_frames().filter(|&r| r <= fade_frames)
+            && let Some(s) = shared.take_queued()
+        {
+            // both tracks are at the mixer rate by now, whatever they were encoded at
+            fade = Some(Crossfade::new(remaining));
+            next = Some(Track::new(s, rs_mode)?);
+        }
+
+        if !ab.is_looping()
+            && let (Some(f), Some(n)) = (fade.as_mut(), next.as_mut())
+        {
+            let frames = n.read(&mut incoming, rs_mode, stats)?;
+            incoming[frames * 2..].fill(0);
+            n.replaygain(&incoming, rg_mode, &mut incoming_chain);
+            f.mix(&mut chain, &incoming_chain);
+            if f.is_done() {
+                // the fade ran longer than the estimated length, hand over now
+                fade = None;
+                current = next.take().unwrap();
+                ab.clear();
+                shared.track.fetch_add(1, Ordering::Relaxed);
             }
-            sys::sceKernelDelayThreadCB(100);
         }
 
-        sceMp3ReleaseMp3Handle(handle);
-        sceMp3TermResource();
+        if let Some(design) = shared.take_eq() {
+            eq.set(&design);
+        }
+        eq.process(&mut chain);
+        // before the tap, so the spectrum shows what's left too
+        karaoke.process(
+            KaraokeMode::from_u8(shared.karaoke.load(Ordering::Relaxed)),
+            &mut chain,
+        );
+
+        // nothing above has been saturated, this is where it's brought under full scale
+        master.process(&mut chain, &shared.master());
+        stats
+            .gain_reduction
+            .store(master.reduction_db().to_bits(), Ordering::Relaxed);
+        for (b, &c) in buf.iter_mut().zip(chain.iter()) {
+            *b = to_i16(c);
+        }
+
+        stretch.set(
+            f32::from_bits(shared.speed.load(Ordering::Relaxed)),
+            shared.pitch.load(Ordering::Relaxed) as i8,
+        );
+        out.clear();
+        stretch.process(&buf, &mut out);
+
+        // position in source time, of what's being heard
+        let position = ab.frame(current.played, latency_frames(&master, &stretch, &writer));
+        shared
+            .position_ms
+            .store(frames_to_ms(position), Ordering::Relaxed);
+        shared.duration_ms.store(
+            current.decoder.duration_ms().unwrap_or(0),
+            Ordering::Relaxed,
+        );
+
+        // the analyzer sees the mix, before volume so the spectrum doesn't shrink with it
+        tap(shared, &out);
+        meter.process(
+            &out,
+            Ballistics::from_u8(shared.ballistics.load(Ordering::Relaxed)),
+        );
+        shared.set_levels(&meter.levels());
+        if shared.loudness_reset.swap(false, Ordering::Relaxed) {
+            loudness.reset();
+        }
+        loudness.process(&out);
+        shared.set_loudness(&loudness.readings());
+
+        gain.process(shared.target_gain(), &mut out, 2);
+
+        // blocks while the mixer has enough buffered, false once our voice was stopped
+        if !writer.write(&out) {
+            break;
+        }
+        stats.buffers.fetch_add(1, Ordering::Relaxed);
     }
 
     Ok(())
@@ -367,26 +637,93 @@ fn mp3_thread_inner(path: &str, shared: &SharedState) -> Result<(), i32> {
 pub struct Mp3Player {
     thid: sys::SceUid,
     shared: *mut SharedState,
+    voice: Voice,
+    io: Option<Arc<ReadaheadCounters>>,
+    /// readahead counters of the queued track
+    queued_io: Option<Arc<ReadaheadCounters>>,
+    /// counters of the track the audio thread took from the queue, become `io` once it plays
+    taken_io: Option<Arc<ReadaheadCounters>>,
+    track: u32,
 }
 
 impl Mp3Player {
     /// The path should be a PSP file path like "ms0:/PSP/GAME/Project/assets/music.mp3"
-    pub fn open(path: &str) -> Result<Self, &'static str> {
+    /// File reads go through a `Readahead` cache so the audio thread doesn't block on I/O.
+    pub fn open(path: &str, mixer: &Mixer) -> Result<Self, Error> {
+        let stream = Readahead::new(AssetStream::open(path)?)?;
+        let counters = stream.counters();
+        let mut player = Self::from_stream(stream, mixer)?;
+        player.io = Some(counters);
+        Ok(player)
+    }
+
+    /// Queue the track to play after the current one, replacing any already queued
+    pub fn enqueue(&mut self, path: &str) -> Result<(), Error> {
+        let stream = Readahead::new(AssetStream::open(path)?)?;
+        let counters = stream.counters();
+        self.enqueue_stream(stream);
+        self.queued_io = Some(counters);
+        Ok(())
+    }
+
+    /// Queue any seekable byte stream, see `enqueue`
+    pub fn enqueue_stream<S: ByteStream + Send + 'static>(&mut self, stream: S) {
+        let shared = unsafe { &*self.shared };
+        if !shared.queue(Box::new(stream)) {
+            // the previous one was already taken
+            self.taken_io = self.queued_io.take();
+        }
+        self.queued_io = None;
+    }
+
+    /// false once the audio thread has taken the queued track
+    pub fn has_queued(&self) -> bool {
+        let shared = unsafe { &*self.shared };
+        !shared.queued.load(Ordering::Acquire).is_null()
+    }
+
+    /// How many queued tracks playback has moved on to
+    pub fn track_number(&self) -> u32 {
+        self.track
+    }
+
+    /// Crossfade length between consecutive tracks, 0 for none
+    pub fn set_crossfade(&self, secs: u32) {
+        let shared = unsafe { &*self.shared };
+        let ms = secs.min(MAX_CROSSFADE_SECS) * 1000;
+        shared.crossfade_ms.store(ms, Ordering::Relaxed);
+    }
+
+    /// Play an in-memory MP3, e.g. one embedded with `include_bytes!`
+    #[allow(dead_code)]
+    pub fn from_memory(data: &'static [u8], mixer: &Mixer) -> Result<Self, Error> {
+        Self::from_stream(SliceReader::new(data), mixer)
+    }
+
+    /// Play from any seekable byte stream. The stream is moved to the audio thread,
+    /// which feeds its own voice on `mixer`.
+    pub fn from_stream<S: ByteStream + Send + 'static>(
+        stream: S,
+        mixer: &Mixer,
+    ) -> Result<Self, Error> {
+        let (writer, voice) = mixer.stream(1.0, 0.0).ok_or(Error::Audio(SCE_ERROR_BUSY))?;
+
         let shared = Box::new(SharedState::new());
         let shared_ptr = Box::into_raw(shared);
 
         let args = Box::new(ThreadArgs {
-            path: String::from(path),
+            stream: Box::new(stream),
+            writer,
             shared: shared_ptr,
         });
         let args_ptr = Box::into_raw(args);
 
         let thid = unsafe {
             sys::sceKernelCreateThread(
-                b"mp3_play_thread\0".as_ptr(),
+                c"mp3_play_thread".as_ptr().cast(),
                 mp3_thread_main,
-                0x1F,  // Priority 31, same as C code
-                0x800, // 2KB stack, same as C code
+                0x1F,   // Priority 31, same as C code
+                0x4000, // 16KB, the C code's 2KB is too tight with two decoders and tags
                 sys::ThreadAttributes::USER | sys::ThreadAttributes::VFPU,
                 ptr::null_mut(),
             )
@@ -397,7 +734,7 @@ impl Mp3Player {
                 drop(Box::from_raw(args_ptr));
                 drop(Box::from_raw(shared_ptr));
             }
-            return Err("Failed to create audio thread");
+            return Err(Error::Thread(thid.0));
         }
 
         let result = unsafe {
@@ -414,53 +751,74 @@ impl Mp3Player {
                 drop(Box::from_raw(args_ptr));
                 drop(Box::from_raw(shared_ptr));
             }
-            return Err("Failed to start audio thread");
+            return Err(Error::Thread(result));
         }
 
         Ok(Self {
             thid,
             shared: shared_ptr,
+            voice,
+            io: None,
+            queued_io: None,
+            taken_io: None,
+            track: 0,
         })
     }
 
     /// - Ok(true) if still playing
     /// - Ok(false) if playback finished
-    /// - Err with error message if playback failed
-    pub fn tick(&mut self) -> Result<bool, &'static str> {
+    /// - Err with the error that stopped the audio thread
+    pub fn tick(&mut self) -> Result<bool, Error> {
         let shared = unsafe { &*self.shared };
 
+        if self.queued_io.is_some() && !self.has_queued() {
+            self.taken_io = self.queued_io.take();
+        }
+        let track = shared.track.load(Ordering::Relaxed);
+        if track != self.track {
+            self.track = track;
+            self.io = self.taken_io.take();
+        }
+
         if shared.error.load(Ordering::Relaxed) {
-            return Err("MP3 playback error");
+            return Err(shared.last_error());
         }
 
         Ok(!shared.finished.load(Ordering::Relaxed))
     }
 
-    /// copy latest `FFT_SIZE` samples into `out` as normalized f32 in [-1.0, 1.0].
-    /// returns number of samples written (FFT_SIZE) or 0 on error.
-    pub fn snapshot_pcm(&self, out: &mut [f32]) -> usize {
-        if out.len() < FFT_SIZE {
-            return 0;
-        }
+    /// audio thread timings and counters for the debug overlay
+    pub fn stats(&self) -> PlayerStats {
         let shared = unsafe { &*self.shared };
-        let write = shared.pcm_write.load(Ordering::Relaxed) as isize;
-        let start = write - FFT_SIZE as isize;
+        shared
+            .stats
+            .snapshot(self.io.as_ref().map(|c| c.snapshot()))
+    }
 
-        unsafe {
-            for i in 0..FFT_SIZE {
-                let idx = ((start + i as isize) % FFT_SIZE as isize + FFT_SIZE as isize)
-                    % FFT_SIZE as isize;
-                let s = PCM_RING.0[idx as usize];
-                out[i] = s as f32 / i16::MAX as f32;
-            }
-        }
-        FFT_SIZE
+    /// Left and right levels of what's playing, before the volume
+    pub fn levels(&self) -> [ChannelLevel; 2] {
+        let shared = unsafe { &*self.shared };
+        shared.levels()
+    }
+
+    /// EBU R128 readings of what's playing, before the volume
+    pub fn loudness(&self) -> Loudness {
+        let shared = unsafe { &*self.shared };
+        shared.loudness()
+    }
+
+    /// Start the integrated loudness, range and true peak over
+    pub fn reset_loudness(&self) {
+        let shared = unsafe { &*self.shared };
+        shared.loudness_reset.store(true, Ordering::Relaxed);
     }
 
-    /// returns last computed level 0..100
-    pub fn level(&self) -> i32 {
+    /// How the level meter moves
+    pub fn set_ballistics(&self, ballistics: Ballistics) {
         let shared = unsafe { &*self.shared };
-        shared.level.load(Ordering::Relaxed)
+        shared
+            .ballistics
+            .store(ballistics.to_u8(), Ordering::Relaxed);
     }
 
     /// return the raw shared pointer for external threads to snapshot PCM
@@ -469,18 +827,164 @@ impl Mp3Player {
         self.shared as *mut core::ffi::c_void
     }
 
+    /// Set the volume, 0.0 (silent) to 1.0 (full scale). Also unmutes.
+    pub fn set_volume(&self, volume: f32) {
+        let shared = unsafe { &*self.shared };
+        let volume = volume.clamp(0.0, 1.0);
+        shared.volume.store(volume.to_bits(), Ordering::Relaxed);
+        shared.muted.store(false, Ordering::Relaxed);
+    }
+
+    pub fn volume(&self) -> f32 {
+        let shared = unsafe { &*self.shared };
+        f32::from_bits(shared.volume.load(Ordering::Relaxed))
+    }
+
+    /// Silence output without losing the volume setting
+    pub fn mute(&self) {
+        let shared = unsafe { &*self.shared };
+        shared.muted.store(true, Ordering::Relaxed);
+    }
+
+    pub fn unmute(&self) {
+        let shared = unsafe { &*self.shared };
+        shared.muted.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        let shared = unsafe { &*self.shared };
+        shared.muted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_replaygain_mode(&self, mode: ReplayGainMode) {
+        let shared = unsafe { &*self.shared };
+        shared
+            .replaygain_mode
+            .store(mode.to_u8(), Ordering::Relaxed);
+    }
+
+    /// Quality of the conversion to the mixer rate, for tracks not already at it
+    pub fn set_resample_mode(&self, mode: ResampleMode) {
+        let shared = unsafe { &*self.shared };
+        shared.resample_mode.store(mode.to_u8(), Ordering::Relaxed);
+    }
+
+    /// Replace the equalizer, applied to the mix before the analyzer tap
+    pub fn set_eq(&self, design: EqDesign) {
+        let shared = unsafe { &*self.shared };
+        shared.set_eq(design);
+    }
+
+    /// Limiter and soft clip at the end of the track chain, where ReplayGain,
+    /// EQ and crossfades can push past full scale
+    pub fn set_master(&self, settings: &MasterSettings) {
+        let shared = unsafe { &*self.shared };
+        shared.limiter.store(settings.limiter, Ordering::Relaxed);
+        shared
+            .threshold_db
+            .store(settings.threshold_db.to_bits(), Ordering::Relaxed);
+        shared
+            .release_ms
+            .store(settings.release_ms.to_bits(), Ordering::Relaxed);
+        shared
+            .soft_clip
+            .store(settings.soft_clip, Ordering::Relaxed);
+    }
+
+    /// Center channel (vocal) removal, heard and analyzed
+    pub fn set_karaoke(&self, mode: KaraokeMode) {
+        let shared = unsafe { &*self.shared };
+        shared.karaoke.store(mode.to_u8(), Ordering::Relaxed);
+    }
+
+    /// Playback speed, `MIN_SPEED..=MAX_SPEED`, pitch stays the same
+    pub fn set_speed(&self, speed: f32) {
+        let shared = unsafe { &*self.shared };
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        shared.speed.store(speed.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn speed(&self) -> f32 {
+        let shared = unsafe { &*self.shared };
+        f32::from_bits(shared.speed.load(Ordering::Relaxed))
+    }
+
+    /// Shift the pitch by `semitones` without changing the speed
+    pub fn set_pitch(&self, semitones: i8) {
+        let shared = unsafe { &*self.shared };
+        let semitones = semitones.clamp(-MAX_SEMITONES, MAX_SEMITONES);
+        shared.pitch.store(semitones as i32, Ordering::Relaxed);
+    }
+
+    /// Position in the current track in ms, in the track's own time
+    /// whatever the playback speed
+    pub fn position_ms(&self) -> u32 {
+        let shared = unsafe { &*self.shared };
+        shared.position_ms.load(Ordering::Relaxed)
+    }
+
+    /// Pause without losing anything buffered, the A-B loop included
+    pub fn pause(&self) {
+        self.voice.set_paused(true);
+    }
+
+    pub fn resume(&self) {
+        self.voice.set_paused(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.voice.is_paused()
+    }
+
+    /// Set loop point A at the current position, then B, then clear the loop
+    pub fn mark_loop(&self) {
+        let shared = unsafe { &*self.shared };
+        shared.loop_mark.store(true, Ordering::Relaxed);
+    }
+
+    #[allow(dead_code)]
+    pub fn clear_loop(&self) {
+        let shared = unsafe { &*self.shared };
+        shared.loop_clear.store(true, Ordering::Relaxed);
+    }
+
+    /// A-B loop points in ms, B is None while only A is set
+    pub fn loop_points(&self) -> Option<(u32, Option<u32>)> {
+        let shared = unsafe { &*self.shared };
+        let point = |p: &AtomicU32| Some(p.load(Ordering::Relaxed)).filter(|&ms| ms != NO_POINT);
+        Some((point(&shared.loop_a_ms)?, point(&shared.loop_b_ms)))
+    }
+
+    /// Length of the current track in ms, if it could be estimated
+    pub fn duration_ms(&self) -> Option<u32> {
+        let shared = unsafe { &*self.shared };
+        match shared.duration_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(ms),
+        }
+    }
+
+    /// gain ReplayGain is currently applying, in dB (0 when off or untagged)
+    pub fn replaygain_db(&self) -> f32 {
+        let shared = unsafe { &*self.shared };
+        f32::from_bits(shared.replaygain_db.load(Ordering::Relaxed))
+    }
+
     /// Stop playback
     #[allow(dead_code)]
     pub fn stop(&mut self) {
         let shared = unsafe { &*self.shared };
         shared.stop_requested.store(true, Ordering::Relaxed);
+        self.voice.stop();
     }
 }
 
 /// snapshot PCM samples using a raw shared pointer returned by `Mp3Player::raw_shared_ptr`
-/// copies `FFT_SIZE` samples into `out` as normalized f32 in [-1.0, 1.0]
-pub fn snapshot_from_shared(shared_ptr: *mut core::ffi::c_void, out: &mut [f32]) -> usize {
-    if out.len() < FFT_SIZE {
+/// copies the latest `out.len()` samples into `out` as they are in the ring,
+/// at most `2 * MAX_FFT_SIZE`
+pub fn snapshot_from_shared(shared_ptr: *mut core::ffi::c_void, out: &mut [i16]) -> usize {
+    let n = out.len();
+    if n > PCM_RING_SIZE {
         return 0;
     }
     if shared_ptr.is_null() {
@@ -488,23 +992,23 @@ pub fn snapshot_from_shared(shared_ptr: *mut core::ffi::c_void, out: &mut [f32])
     }
     let shared = unsafe { &*(shared_ptr as *mut SharedState) };
     let write = shared.pcm_write.load(Ordering::Relaxed) as isize;
-    let start = write - FFT_SIZE as isize;
+    let start = write - n as isize;
 
     unsafe {
-        for i in 0..FFT_SIZE {
-            let idx =
-                ((start + i as isize) % FFT_SIZE as isize + FFT_SIZE as isize) % FFT_SIZE as isize;
-            let s = PCM_RING.0[idx as usize];
-            out[i] = s as f32 / i16::MAX as f32;
+        for (i, s) in out.iter_mut().enumerate() {
+            let idx = (start + i as isize).rem_euclid(PCM_RING_SIZE as isize);
+            *s = PCM_RING.0[idx as usize];
         }
     }
-    FFT_SIZE
+    n
 }
 
 impl Drop for Mp3Player {
     fn drop(&mut self) {
         let shared = unsafe { &*self.shared };
         shared.stop_requested.store(true, Ordering::Relaxed);
+        // also wakes the audio thread if it's blocked on a full stream
+        self.voice.stop();
 
         unsafe {
             let _ = sys::sceKernelWaitThreadEnd(self.thid, ptr::null_mut());
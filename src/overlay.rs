@@ -0,0 +1,214 @@
+// on-screen debug text drawn with the GU debug font (8x8)
+
+use core::fmt::{self, Write};
+
+use psp::sys;
+
+use crate::dsp::loudness::Loudness;
+use crate::stats::{MixerStats, PlayerStats, Timing};
+
+pub const LINE_HEIGHT: i32 = 9;
+
+const WHITE: u32 = 0xFFFFFFFF;
+const RED: u32 = 0xFF4040FF; // ABGR
+
+/// Fixed-size, null terminated line buffer for `sceGuDebugPrint`
+struct Line {
+    buf: [u8; 64],
+    len: usize,
+}
+
+impl Line {
+    fn new() -> Self {
+        Self {
+            buf: [0; 64],
+            len: 0,
+        }
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // keep the last byte for the terminator, silently truncate the rest
+        let room = self.buf.len() - 1 - self.len;
+        let n = s.len().min(room);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// Queue a line of text, call `flush` once the frame's GU list has finished
+pub fn print(x: i32, y: i32, color: u32, args: fmt::Arguments) {
+    let mut line = Line::new();
+    let _ = line.write_fmt(args);
+    line.buf[line.len] = 0;
+    unsafe { sys::sceGuDebugPrint(x, y, color, line.buf.as_ptr()) };
+}
+
+/// Draw queued text into the current draw buffer
+pub fn flush() {
+    unsafe { sys::sceGuDebugFlush() };
+}
+
+fn timing_line(x: i32, y: i32, name: &str, t: &Timing) {
+    print(
+        x,
+        y,
+        WHITE,
+        format_args!(
+            "{:<6} {:>6} {:>6} {:>6}",
+            name, t.last_us, t.avg_us, t.max_us
+        ),
+    );
+}
+
+/// Audio thread telemetry, returns the y below the last line
+pub fn draw_player_stats(x: i32, y: i32, stats: &PlayerStats) -> i32 {
+    let mut y = y;
+    print(
+        x,
+        y,
+        WHITE,
+        format_args!("{:<6} {:>6} {:>6} {:>6}", "us", "last", "avg", "max"),
+    );
+    y += LINE_HEIGHT;
+    timing_line(x, y, "decode", &stats.decode);
+    y += LINE_HEIGHT;
+    timing_line(x, y, "fill", &stats.fill);
+    y += LINE_HEIGHT;
+
+    let color = if stats.decode_errors > 0 { RED } else { WHITE };
+    print(
+        x,
+        y,
+        color,
+        format_args!("buffers {} errors {}", stats.buffers, stats.decode_errors),
+    );
+    y += LINE_HEIGHT;
+
+    // the track limiter, where ReplayGain and EQ boosts get caught
+    draw_gain_reduction(x, y, stats.gain_reduction_db);
+    y += LINE_HEIGHT;
+
+    if let Some(io) = stats.io {
+        let color = if io.stalls > 0 { RED } else { WHITE };
+        print(
+            x,
+            y,
+            color,
+            format_args!(
+                "io hits {} stalls {} ({}/{} us)",
+                io.hits, io.stalls, io.stall_us, io.max_stall_us
+            ),
+        );
+        y += LINE_HEIGHT;
+    }
+
+    y
+}
+
+/// Mixer thread telemetry, returns the y below the last line
+pub fn draw_mixer_stats(x: i32, y: i32, stats: &MixerStats) -> i32 {
+    let mut y = y;
+    timing_line(x, y, "output", &stats.output);
+    y += LINE_HEIGHT;
+
+    let color = if stats.late_buffers > 0 || stats.underruns > 0 {
+        RED
+    } else {
+        WHITE
+    };
+    print(
+        x,
+        y,
+        color,
+        format_args!(
+            "mix {} late {} under {} voices {}",
+            stats.buffers, stats.late_buffers, stats.underruns, stats.voices
+        ),
+    );
+    y += LINE_HEIGHT;
+
+    draw_gain_reduction(x, y, stats.gain_reduction_db);
+    y + LINE_HEIGHT
+}
+
+/// Limiter gain reduction as a bar, one cell per dB
+pub fn draw_gain_reduction(x: i32, y: i32, db: f32) {
+    const CELLS: usize = 12;
+    let mut bar = [b' '; CELLS];
+    // ignore float dust from a gain that's only just below 1
+    let lit = (libm::ceilf(-db - 0.1) as usize).min(CELLS);
+    bar[..lit].fill(b'#');
+    let bar = core::str::from_utf8(&bar).unwrap_or("");
+    let color = if lit > 0 { RED } else { WHITE };
+    print(x, y, color, format_args!("gr {:>5.1} dB [{}]", db, bar));
+}
+
+/// "m:ss / m:ss" for the current track, plus the speed when it isn't 1x
+pub fn draw_time(
+    x: i32,
+    y: i32,
+    position_ms: u32,
+    duration_ms: Option<u32>,
+    speed: f32,
+    paused: bool,
+) {
+    let mut line = Line::new();
+    let (m, s) = (position_ms / 60_000, position_ms / 1000 % 60);
+    let _ = write!(line, "{}:{:02}", m, s);
+    if let Some(d) = duration_ms {
+        let _ = write!(line, " / {}:{:02}", d / 60_000, d / 1000 % 60);
+    }
+    if speed != 1.0 {
+        let _ = write!(line, "  {:.2}x", speed);
+    }
+    if paused {
+        let _ = line.write_str("  paused");
+    }
+    line.buf[line.len] = 0;
+    unsafe { sys::sceGuDebugPrint(x, y, WHITE, line.buf.as_ptr()) };
+}
+
+/// A reading, or "-inf" before there is one
+struct Lu(f32);
+
+impl fmt::Display for Lu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_finite() {
+            write!(f, "{:>6.1}", self.0)
+        } else {
+            f.write_str("  -inf")
+        }
+    }
+}
+
+/// Text side of the loudness screen, returns the y below the last line
+pub fn draw_loudness(x: i32, y: i32, l: &Loudness) -> i32 {
+    let lines = [
+        ("M", l.momentary, "LUFS"),
+        ("S", l.short_term, "LUFS"),
+        ("I", l.integrated, "LUFS"),
+        ("LRA", l.range, "LU"),
+        ("TP", l.true_peak, "dBTP"),
+    ];
+    let mut y = y;
+    for (name, value, unit) in lines {
+        // EBU R128 allows -1 dBTP
+        let color = if name == "TP" && value > -1.0 {
+            RED
+        } else {
+            WHITE
+        };
+        print(
+            x,
+            y,
+            color,
+            format_args!("{:<3} {} {}", name, Lu(value), unit),
+        );
+        y += LINE_HEIGHT + 3;
+    }
+    print(x, y, WHITE, format_args!("target -23 LUFS, O resets"));
+    y + LINE_HEIGHT
+}
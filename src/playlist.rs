@@ -0,0 +1,69 @@
+// the .mp3 files next to the one we started with, played in name order
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use psp::sys::{self, SceIoDirent};
+
+use crate::error::Error;
+use crate::utils::to_c_path;
+
+pub struct Playlist {
+    paths: Vec<String>,
+    next: usize,
+}
+
+impl Playlist {
+    /// Every .mp3 in `first`'s directory, starting with `first`.
+    /// Falls back to just `first` if the directory can't be listed.
+    pub fn from_file(first: &str) -> Self {
+        let dir = first.rfind('/').map_or("", |i| &first[..i]);
+        let mut paths = list_mp3s(dir).unwrap_or_default();
+        paths.sort();
+
+        let start = paths.iter().position(|p| p == first);
+        let next = match start {
+            Some(i) => i,
+            None => {
+                paths.insert(0, String::from(first));
+                0
+            }
+        };
+        Self { paths, next }
+    }
+
+    /// Path of the next track, None at the end of the list
+    pub fn next(&mut self) -> Option<&str> {
+        let path = self.paths.get(self.next)?;
+        self.next += 1;
+        Some(path)
+    }
+}
+
+fn list_mp3s(dir: &str) -> Result<Vec<String>, Error> {
+    let dir_z = to_c_path(dir);
+    let fd = unsafe { sys::sceIoDopen(dir_z.as_ptr()) };
+    if fd.0 < 0 {
+        return Err(Error::Io(fd.0));
+    }
+
+    let mut paths = Vec::new();
+    let mut entry: SceIoDirent = unsafe { core::mem::zeroed() };
+    while unsafe { sys::sceIoDread(fd, &mut entry) } > 0 {
+        let len = entry.d_name.iter().position(|&b| b == 0).unwrap_or(0);
+        let Ok(name) = core::str::from_utf8(&entry.d_name[..len]) else {
+            continue;
+        };
+        let is_mp3 =
+            name.len() > 4 && name.as_bytes()[name.len() - 4..].eq_ignore_ascii_case(b".mp3");
+        if is_mp3 {
+            let mut path = String::with_capacity(dir.len() + 1 + name.len());
+            path.push_str(dir);
+            path.push('/');
+            path.push_str(name);
+            paths.push(path);
+        }
+    }
+
+    unsafe { sys::sceIoDclose(fd) };
+    Ok(paths)
+}
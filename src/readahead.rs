@@ -0,0 +1,424 @@
+// background readahead so the audio thread never waits on the memory stick
+//
+// a dedicated i/o thread owns the underlying stream and keeps the block the
+// reader is in plus the ones after it loaded. the reader only ever copies out
+// of ready blocks and only stalls when it outruns the i/o thread (or seeks).
+//
+// sizing: a memory stick can go quiet for a few hundred ms (wear levelling,
+// a Pro Duo adapter, another thread hitting the stick), and the cache has to
+// carry the decoder through that. 512KB is over 12s of a 320kbps mp3, so even
+// a stall that long only eats into the lead, and it costs 1MB during a
+// crossfade when two tracks are open.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, AtomicU32, Ordering};
+use core::{ffi::c_void, ptr};
+
+extern crate alloc;
+use alloc::{boxed::Box, sync::Arc, vec};
+
+use psp::sys::{self, SceUid};
+
+use crate::error::Error;
+use crate::io::{ByteRead, ByteSeek, ByteStream, SeekFrom};
+
+const BLOCK_SIZE: usize = 64 * 1024;
+/// the block being read plus the prefetch ahead of it, 512KB in all
+const BLOCK_COUNT: usize = 8;
+
+// block states
+const EMPTY: u8 = 0;
+const LOADING: u8 = 1;
+const READY: u8 = 2;
+const IN_USE: u8 = 3; // pinned by the reader while it copies out
+const FAILED: u8 = 4; // the load failed, not retried until the reader has seen it
+
+// how long either side sleeps before re-checking, in microseconds
+const WAIT_TIMEOUT_US: u32 = 20_000;
+
+struct Block {
+    state: AtomicU8,
+    /// block number, covers `index * BLOCK_SIZE..` (only changes while LOADING)
+    index: AtomicU32,
+    len: AtomicU32,
+    data: UnsafeCell<Box<[u8]>>,
+}
+
+/// Cache counters, readable from any thread while playback runs
+#[derive(Default)]
+pub struct ReadaheadCounters {
+    hits: AtomicU32,
+    stalls: AtomicU32,
+    stall_us: AtomicU32,
+    max_stall_us: AtomicU32,
+    bytes_read: AtomicU32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadaheadStats {
+    /// reads served straight from a ready block
+    pub hits: u32,
+    /// reads that had to wait for the i/o thread
+    pub stalls: u32,
+    /// total time spent waiting, in microseconds
+    pub stall_us: u32,
+    pub max_stall_us: u32,
+    /// bytes pulled from the underlying stream
+    pub bytes_read: u32,
+}
+
+impl ReadaheadCounters {
+    pub fn snapshot(&self) -> ReadaheadStats {
+        ReadaheadStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            stalls: self.stalls.load(Ordering::Relaxed),
+            stall_us: self.stall_us.load(Ordering::Relaxed),
+            max_stall_us: self.max_stall_us.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Inner<S> {
+    stream: UnsafeCell<S>,
+    blocks: [Block; BLOCK_COUNT],
+    size: u64,
+    /// block the reader is currently in
+    want: AtomicU32,
+    stop: AtomicBool,
+    /// code of the last failed load
+    error: AtomicI32,
+    /// signalled by the reader when it moves to another block
+    wake: SceUid,
+    /// signalled by the i/o thread after each load
+    ready: SceUid,
+    counters: Arc<ReadaheadCounters>,
+}
+
+// the stream is only touched by the i/o thread, block data is guarded by `state`
+unsafe impl<S: Send> Send for Inner<S> {}
+unsafe impl<S: Send> Sync for Inner<S> {}
+
+impl<S> Inner<S> {
+    fn block_count(&self) -> u32 {
+        self.size.div_ceil(BLOCK_SIZE as u64) as u32
+    }
+
+    fn holds(&self, block: &Block, index: u32) -> bool {
+        block.state.load(Ordering::Acquire) != EMPTY && block.index.load(Ordering::Relaxed) == index
+    }
+
+    /// Pin the ready block holding `index` so the i/o thread leaves it alone
+    fn pin(&self, index: u32) -> Option<&Block> {
+        for block in self.blocks.iter() {
+            if block.index.load(Ordering::Relaxed) != index {
+                continue;
+            }
+            if block
+                .state
+                .compare_exchange(READY, IN_USE, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                continue;
+            }
+            // it may have been recycled between the index check and the pin
+            if block.index.load(Ordering::Relaxed) == index {
+                return Some(block);
+            }
+            block.state.store(READY, Ordering::Release);
+        }
+        None
+    }
+
+    /// Error of a failed load of `index`, the block is free to retry after this
+    fn take_failed(&self, index: u32) -> Option<i32> {
+        for block in self.blocks.iter() {
+            if block.index.load(Ordering::Relaxed) != index
+                || block
+                    .state
+                    .compare_exchange(FAILED, EMPTY, Ordering::Acquire, Ordering::Relaxed)
+                    .is_err()
+            {
+                continue;
+            }
+            if block.index.load(Ordering::Relaxed) == index {
+                return Some(self.error.load(Ordering::Relaxed));
+            }
+            block.state.store(FAILED, Ordering::Release);
+        }
+        None
+    }
+}
+
+impl<S: ByteStream> Inner<S> {
+    /// Load one missing block, returns false if there was nothing to do or
+    /// the load failed, so the i/o thread waits before going on
+    fn io_step(&self) -> bool {
+        let want = self.want.load(Ordering::Acquire);
+        let wanted = want..want.saturating_add(BLOCK_COUNT as u32);
+        let count = self.block_count();
+
+        for target in wanted.clone() {
+            if target >= count {
+                break;
+            }
+            if self.blocks.iter().any(|b| self.holds(b, target)) {
+                continue;
+            }
+
+            // recycle a block that's empty or holds data the reader has left behind
+            let victim = self.blocks.iter().find(|b| {
+                let from = b.state.load(Ordering::Acquire);
+                let stale = from == EMPTY
+                    || ((from == READY || from == FAILED)
+                        && !wanted.contains(&b.index.load(Ordering::Relaxed)));
+                stale
+                    && b.state
+                        .compare_exchange(from, LOADING, Ordering::Acquire, Ordering::Relaxed)
+                        .is_ok()
+            });
+            let Some(block) = victim else {
+                return false;
+            };
+
+            block.index.store(target, Ordering::Relaxed);
+            let loaded = self.load(block, target);
+            match loaded {
+                Ok(len) => {
+                    block.len.store(len as u32, Ordering::Relaxed);
+                    block.state.store(READY, Ordering::Release);
+                    self.counters
+                        .bytes_read
+                        .fetch_add(len as u32, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    // kept as FAILED so a dead stick doesn't get hammered
+                    // at a priority above the audio threads
+                    self.error.store(e.code(), Ordering::Relaxed);
+                    block.state.store(FAILED, Ordering::Release);
+                }
+            }
+            unsafe { sys::sceKernelSignalSema(self.ready, 1) };
+            return loaded.is_ok();
+        }
+
+        false
+    }
+
+    fn load(&self, block: &Block, index: u32) -> Result<usize, Error> {
+        // only the i/o thread gets here, and the block is LOADING so the reader won't touch it
+        let stream = unsafe { &mut *self.stream.get() };
+        let data = unsafe { &mut *block.data.get() };
+        stream.seek(SeekFrom::Start(index as u64 * BLOCK_SIZE as u64))?;
+        stream.read_full(data)
+    }
+}
+
+/// Wraps a stream with a background i/o thread and a prefetch cache
+pub struct Readahead<S: ByteStream + Send + 'static> {
+    inner: Arc<Inner<S>>,
+    thid: SceUid,
+    pos: u64,
+}
+
+impl<S: ByteStream + Send + 'static> Readahead<S> {
+    pub fn new(mut stream: S) -> Result<Self, Error> {
+        let size = stream.size()?;
+
+        let wake = unsafe {
+            sys::sceKernelCreateSema(c"ra_wake".as_ptr().cast(), 0, 0, 1, ptr::null_mut())
+        };
+        if wake.0 < 0 {
+            return Err(Error::Thread(wake.0));
+        }
+        let ready = unsafe {
+            sys::sceKernelCreateSema(c"ra_ready".as_ptr().cast(), 0, 0, 1, ptr::null_mut())
+        };
+        if ready.0 < 0 {
+            unsafe { sys::sceKernelDeleteSema(wake) };
+            return Err(Error::Thread(ready.0));
+        }
+
+        let inner = Arc::new(Inner {
+            stream: UnsafeCell::new(stream),
+            blocks: core::array::from_fn(|_| Block {
+                state: AtomicU8::new(EMPTY),
+                index: AtomicU32::new(0),
+                len: AtomicU32::new(0),
+                data: UnsafeCell::new(vec![0u8; BLOCK_SIZE].into_boxed_slice()),
+            }),
+            size,
+            want: AtomicU32::new(0),
+            stop: AtomicBool::new(false),
+            error: AtomicI32::new(0),
+            wake,
+            ready,
+            counters: Arc::new(ReadaheadCounters::default()),
+        });
+
+        let delete_semas = || unsafe {
+            sys::sceKernelDeleteSema(wake);
+            sys::sceKernelDeleteSema(ready);
+        };
+
+        let thid = unsafe {
+            sys::sceKernelCreateThread(
+                c"readahead_thread".as_ptr().cast(),
+                io_thread_main::<S>,
+                0x18,   // above the audio thread so prefetch keeps up
+                0x1000, // 4KB stack
+                sys::ThreadAttributes::USER,
+                ptr::null_mut(),
+            )
+        };
+        if thid.0 < 0 {
+            delete_semas();
+            return Err(Error::Thread(thid.0));
+        }
+
+        let arg_ptr = Box::into_raw(Box::new(inner.clone()));
+        let result = unsafe {
+            sys::sceKernelStartThread(
+                thid,
+                core::mem::size_of::<*mut Arc<Inner<S>>>(),
+                &arg_ptr as *const _ as *mut c_void,
+            )
+        };
+        if result < 0 {
+            unsafe {
+                let _ = sys::sceKernelDeleteThread(thid);
+                drop(Box::from_raw(arg_ptr));
+            }
+            delete_semas();
+            return Err(Error::Thread(result));
+        }
+
+        Ok(Self {
+            inner,
+            thid,
+            pos: 0,
+        })
+    }
+
+    /// Shared handle to the cache counters
+    pub fn counters(&self) -> Arc<ReadaheadCounters> {
+        self.inner.counters.clone()
+    }
+
+    fn set_want(&self, index: u32) {
+        if self.inner.want.swap(index, Ordering::AcqRel) != index {
+            unsafe { sys::sceKernelSignalSema(self.inner.wake, 1) };
+        }
+    }
+}
+
+impl<S: ByteStream + Send + 'static> ByteRead for Readahead<S> {
+    fn read(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+        let inner = &*self.inner;
+        if out.is_empty() || self.pos >= inner.size {
+            return Ok(0);
+        }
+
+        let index = (self.pos / BLOCK_SIZE as u64) as u32;
+        let offset = (self.pos % BLOCK_SIZE as u64) as usize;
+        self.set_want(index);
+
+        let mut stall_start: Option<u32> = None;
+        let n = loop {
+            if let Some(block) = inner.pin(index) {
+                let len = block.len.load(Ordering::Relaxed) as usize;
+                let n = out.len().min(len.saturating_sub(offset));
+                let data = unsafe { &*block.data.get() };
+                out[..n].copy_from_slice(&data[offset..offset + n]);
+                block.state.store(READY, Ordering::Release);
+                break n;
+            }
+
+            if let Some(err) = inner.take_failed(index) {
+                return Err(Error::Io(err));
+            }
+
+            if stall_start.is_none() {
+                stall_start = Some(unsafe { sys::sceKernelGetSystemTimeLow() });
+                inner.counters.stalls.fetch_add(1, Ordering::Relaxed);
+            }
+            let mut timeout = WAIT_TIMEOUT_US;
+            unsafe {
+                sys::sceKernelSignalSema(inner.wake, 1);
+                sys::sceKernelWaitSema(inner.ready, 1, &mut timeout);
+            }
+        };
+
+        match stall_start {
+            Some(start) => {
+                let waited = unsafe { sys::sceKernelGetSystemTimeLow() }.wrapping_sub(start);
+                inner.counters.stall_us.fetch_add(waited, Ordering::Relaxed);
+                inner
+                    .counters
+                    .max_stall_us
+                    .fetch_max(waited, Ordering::Relaxed);
+            }
+            None => {
+                inner.counters.hits.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<S: ByteStream + Send + 'static> ByteSeek for Readahead<S> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let target = match pos {
+            SeekFrom::Start(off) => off as i64,
+            SeekFrom::End(off) => self.inner.size as i64 + off,
+            SeekFrom::Current(off) => self.pos as i64 + off,
+        };
+        if target < 0 {
+            return Err(Error::Io(crate::error::SCE_ERROR_ERRNO_EINVAL));
+        }
+        self.pos = target as u64;
+        // start fetching the new spot right away
+        if self.pos < self.inner.size {
+            self.set_want((self.pos / BLOCK_SIZE as u64) as u32);
+        }
+        Ok(self.pos)
+    }
+
+    fn size(&mut self) -> Result<u64, Error> {
+        Ok(self.inner.size)
+    }
+}
+
+impl<S: ByteStream + Send + 'static> Drop for Readahead<S> {
+    fn drop(&mut self) {
+        self.inner.stop.store(true, Ordering::Relaxed);
+        unsafe {
+            sys::sceKernelSignalSema(self.inner.wake, 1);
+            let _ = sys::sceKernelWaitThreadEnd(self.thid, ptr::null_mut());
+            let _ = sys::sceKernelDeleteThread(self.thid);
+            sys::sceKernelDeleteSema(self.inner.wake);
+            sys::sceKernelDeleteSema(self.inner.ready);
+        }
+    }
+}
+
+extern "C" fn io_thread_main<S: ByteStream + Send + 'static>(
+    _args: usize,
+    argp: *mut c_void,
+) -> i32 {
+    let arg_ptr = unsafe { *(argp as *const *mut Arc<Inner<S>>) };
+    let inner: Box<Arc<Inner<S>>> = unsafe { Box::from_raw(arg_ptr) };
+
+    while !inner.stop.load(Ordering::Relaxed) {
+        if !inner.io_step() {
+            let mut timeout = WAIT_TIMEOUT_US;
+            unsafe { sys::sceKernelWaitSema(inner.wake, 1, &mut timeout) };
+        }
+    }
+
+    // drop our reference before the thread goes away
+    drop(inner);
+    0
+}
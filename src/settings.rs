@@ -0,0 +1,298 @@
+// user settings, edited through the menu (START)
+
+use core::fmt::{self, Write};
+
+use crate::bands::{Aggregation, BandLayout};
+use crate::dsp::crossfade::MAX_CROSSFADE_SECS;
+use crate::dsp::dynamics::{MIN_THRESHOLD_DB, MasterSettings, RELEASE_STEPS_MS};
+use crate::dsp::eq::{EqDesign, EqPreset, GRAPHIC_RANGE_DB, MAX_BANDS};
+use crate::dsp::karaoke::KaraokeMode;
+use crate::dsp::meter::Ballistics;
+use crate::dsp::replaygain::ReplayGainMode;
+use crate::dsp::resample::ResampleMode;
+use crate::dsp::stretch::{MAX_SEMITONES, MAX_SPEED, MIN_SPEED};
+use crate::fft::{
+    AnalyzerSettings, DEFAULT_FFT_SIZE, DEFAULT_KAISER_BETA, GRAVITY_STEPS, MAX_CEILING_DB,
+    MAX_FFT_SIZE, MAX_KAISER_BETA, MIN_FFT_SIZE, MIN_FLOOR_DB, MIN_RANGE_DB, SMOOTHING_STEPS_MS,
+    Window,
+};
+use crate::mixer::SAMPLING_RATE;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    pub replaygain: ReplayGainMode,
+    /// overlap between consecutive tracks, 0 = off
+    pub crossfade_secs: u32,
+    /// peak limiter at the end of the track chain and on the mixer's master bus
+    pub limiter: bool,
+    /// dBFS, `MIN_THRESHOLD_DB..=0`
+    pub limiter_threshold_db: i8,
+    /// one of `RELEASE_STEPS_MS`
+    pub limiter_release_ms: u32,
+    pub soft_clip: bool,
+    /// conversion quality for tracks that aren't 44.1kHz
+    pub resample: ResampleMode,
+    /// playback speed in percent, pitch preserved
+    pub speed_pct: u32,
+    /// pitch shift in semitones, speed preserved
+    pub pitch: i8,
+    pub karaoke: KaraokeMode,
+    /// level meter ballistics
+    pub meter: Ballistics,
+    pub eq: EqPreset,
+    /// analyzer FFT points, bigger resolves more and reacts slower
+    pub fft_size: usize,
+    /// analyzer window, Kaiser with the default β, see `window()`
+    pub fft_window: Window,
+    pub kaiser_beta: u8,
+    /// dBFS at the bottom and top of the bars
+    pub floor_db: i8,
+    pub ceiling_db: i8,
+    pub agc: bool,
+    /// how FFT bins are grouped into bars
+    pub bands: BandLayout,
+    pub aggregation: Aggregation,
+    /// bar time constants, one of `SMOOTHING_STEPS_MS`
+    pub attack_ms: u32,
+    pub release_ms: u32,
+    /// one of `GRAVITY_STEPS`, 0 = off
+    pub gravity: u32,
+    /// graphic EQ band gains in dB, used when `eq` is `EqPreset::Graphic`
+    pub graphic: [i8; MAX_BANDS],
+}
+
+const GRAPHIC_LABELS: [&str; MAX_BANDS] = [
+    "EQ 31", "EQ 62", "EQ 125", "EQ 250", "EQ 500", "EQ 1k", "EQ 2k", "EQ 4k", "EQ 8k", "EQ 16k",
+];
+const FIRST_GRAPHIC_ITEM: usize = 23;
+/// percent per speed step
+const SPEED_STEP: u32 = 5;
+/// dB per analyzer floor/ceiling step
+const SCALE_STEP_DB: i8 = 6;
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            replaygain: ReplayGainMode::default(),
+            crossfade_secs: 0,
+            limiter: true,
+            limiter_threshold_db: -1,
+            limiter_release_ms: 100,
+            soft_clip: false,
+            resample: ResampleMode::default(),
+            speed_pct: 100,
+            pitch: 0,
+            karaoke: KaraokeMode::default(),
+            meter: Ballistics::default(),
+            eq: EqPreset::default(),
+            fft_size: DEFAULT_FFT_SIZE,
+            fft_window: Window::default(),
+            kaiser_beta: DEFAULT_KAISER_BETA,
+            floor_db: -90,
+            ceiling_db: 0,
+            agc: false,
+            bands: BandLayout::default(),
+            aggregation: Aggregation::default(),
+            attack_ms: 125,
+            release_ms: 125,
+            gravity: 0,
+            graphic: [0; MAX_BANDS],
+        }
+    }
+}
+
+/// Step through `steps` by `dir` without wrapping
+fn step(steps: &[u32], current: u32, dir: i32) -> u32 {
+    let i = steps.iter().position(|&v| v == current).unwrap_or(0) as i32;
+    steps[(i + dir).clamp(0, steps.len() as i32 - 1) as usize]
+}
+
+/// Step through `all` by `dir`, wrapping around
+fn cycle<T: Copy + PartialEq>(all: &[T], current: T, dir: i32) -> T {
+    let i = all.iter().position(|&v| v == current).unwrap_or(0) as i32;
+    let n = all.len() as i32;
+    all[(i + dir).rem_euclid(n) as usize]
+}
+
+impl Settings {
+    pub const ITEM_COUNT: usize = FIRST_GRAPHIC_ITEM + MAX_BANDS;
+
+    pub fn label(i: usize) -> &'static str {
+        match i {
+            0 => "ReplayGain",
+            1 => "Crossfade",
+            2 => "Limiter",
+            3 => "Threshold",
+            4 => "Release",
+            5 => "Soft clip",
+            6 => "Resampler",
+            7 => "Speed",
+            8 => "Pitch",
+            9 => "Karaoke",
+            10 => "Meter",
+            11 => "EQ",
+            12 => "FFT size",
+            13 => "Window",
+            14 => "Kaiser beta",
+            15 => "Floor",
+            16 => "Ceiling",
+            17 => "AGC",
+            18 => "Bands",
+            19 => "Aggregate",
+            20 => "Bar attack",
+            21 => "Bar release",
+            22 => "Gravity",
+            i if i >= FIRST_GRAPHIC_ITEM => GRAPHIC_LABELS[i - FIRST_GRAPHIC_ITEM],
+            _ => "",
+        }
+    }
+
+    pub fn write_value(&self, i: usize, out: &mut impl Write) -> fmt::Result {
+        match i {
+            0 => out.write_str(self.replaygain.label()),
+            1 if self.crossfade_secs == 0 => out.write_str("off"),
+            1 => write!(out, "{}s", self.crossfade_secs),
+            2 => out.write_str(if self.limiter { "on" } else { "off" }),
+            3 => write!(out, "{} dB", self.limiter_threshold_db),
+            4 => write!(out, "{} ms", self.limiter_release_ms),
+            5 => out.write_str(if self.soft_clip { "on" } else { "off" }),
+            6 => out.write_str(self.resample.label()),
+            7 => write!(out, "{:.2}x", self.speed()),
+            8 => write!(out, "{:+} st", self.pitch),
+            9 => out.write_str(self.karaoke.label()),
+            10 => out.write_str(self.meter.label()),
+            11 => out.write_str(self.eq.label()),
+            // one point per frame after the downmix
+            12 => write!(
+                out,
+                "{} ({} ms)",
+                self.fft_size,
+                self.fft_size as u32 * 1000 / SAMPLING_RATE
+            ),
+            13 => out.write_str(self.fft_window.label()),
+            14 => write!(out, "{}", self.kaiser_beta),
+            15 => write!(out, "{} dB", self.floor_db),
+            16 => write!(out, "{} dB", self.ceiling_db),
+            17 => out.write_str(if self.agc { "on" } else { "off" }),
+            18 => out.write_str(self.bands.label()),
+            19 => out.write_str(self.aggregation.label()),
+            20 => write!(out, "{} ms", self.attack_ms),
+            21 => write!(out, "{} ms", self.release_ms),
+            22 if self.gravity == 0 => out.write_str("off"),
+            22 => write!(out, "{}", self.gravity),
+            i if i >= FIRST_GRAPHIC_ITEM => {
+                write!(out, "{:+} dB", self.graphic[i - FIRST_GRAPHIC_ITEM])
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed_pct as f32 / 100.0
+    }
+
+    pub fn master(&self) -> MasterSettings {
+        MasterSettings {
+            limiter: self.limiter,
+            threshold_db: self.limiter_threshold_db as f32,
+            release_ms: self.limiter_release_ms as f32,
+            soft_clip: self.soft_clip,
+        }
+    }
+
+    pub fn analyzer(&self) -> AnalyzerSettings {
+        AnalyzerSettings {
+            size: self.fft_size,
+            // the chosen Kaiser β
+            window: match self.fft_window {
+                Window::Kaiser(_) => Window::Kaiser(self.kaiser_beta),
+                w => w,
+            },
+            floor_db: self.floor_db as f32,
+            ceiling_db: self.ceiling_db as f32,
+            agc: self.agc,
+            layout: self.bands,
+            aggregation: self.aggregation,
+            // the analyzer downmixes the ring to mono first
+            sampling_rate: SAMPLING_RATE,
+            attack_ms: self.attack_ms as f32,
+            release_ms: self.release_ms as f32,
+            gravity: self.gravity as f32,
+        }
+    }
+
+    pub fn eq_design(&self) -> EqDesign {
+        EqDesign::from_preset(self.eq, &self.graphic)
+    }
+
+    /// Change item `i` one step left (-1) or right (+1)
+    pub fn adjust(&mut self, i: usize, dir: i32) {
+        match i {
+            0 => self.replaygain = cycle(&ReplayGainMode::ALL, self.replaygain, dir),
+            1 => {
+                self.crossfade_secs = self
+                    .crossfade_secs
+                    .saturating_add_signed(dir)
+                    .min(MAX_CROSSFADE_SECS)
+            }
+            2 => self.limiter = !self.limiter,
+            3 => {
+                self.limiter_threshold_db =
+                    (self.limiter_threshold_db + dir as i8).clamp(MIN_THRESHOLD_DB, 0)
+            }
+            // step, don't wrap, from the shortest to the longest
+            4 => self.limiter_release_ms = step(&RELEASE_STEPS_MS, self.limiter_release_ms, dir),
+            5 => self.soft_clip = !self.soft_clip,
+            6 => self.resample = cycle(&ResampleMode::ALL, self.resample, dir),
+            7 => {
+                let (min, max) = ((MIN_SPEED * 100.0) as u32, (MAX_SPEED * 100.0) as u32);
+                self.speed_pct = self
+                    .speed_pct
+                    .saturating_add_signed(dir * SPEED_STEP as i32)
+                    .clamp(min, max)
+            }
+            8 => self.pitch = (self.pitch + dir as i8).clamp(-MAX_SEMITONES, MAX_SEMITONES),
+            9 => self.karaoke = cycle(&KaraokeMode::ALL, self.karaoke, dir),
+            10 => self.meter = cycle(&Ballistics::ALL, self.meter, dir),
+            11 => self.eq = cycle(&EqPreset::ALL, self.eq, dir),
+            12 => {
+                self.fft_size = if dir > 0 {
+                    (self.fft_size * 2).min(MAX_FFT_SIZE)
+                } else {
+                    (self.fft_size / 2).max(MIN_FFT_SIZE)
+                }
+            }
+            13 => self.fft_window = cycle(&Window::ALL, self.fft_window, dir),
+            14 => {
+                self.kaiser_beta = self
+                    .kaiser_beta
+                    .saturating_add_signed(dir as i8)
+                    .min(MAX_KAISER_BETA);
+                // same as the graphic EQ, adjusting β means you want Kaiser
+                self.fft_window = Window::Kaiser(DEFAULT_KAISER_BETA);
+            }
+            // each stops short of squeezing the range under the minimum
+            15 => {
+                self.floor_db = (self.floor_db + dir as i8 * SCALE_STEP_DB)
+                    .clamp(MIN_FLOOR_DB, self.ceiling_db - MIN_RANGE_DB)
+            }
+            16 => {
+                self.ceiling_db = (self.ceiling_db + dir as i8 * SCALE_STEP_DB)
+                    .clamp(self.floor_db + MIN_RANGE_DB, MAX_CEILING_DB)
+            }
+            17 => self.agc = !self.agc,
+            18 => self.bands = cycle(&BandLayout::ALL, self.bands, dir),
+            19 => self.aggregation = cycle(&Aggregation::ALL, self.aggregation, dir),
+            20 => self.attack_ms = step(&SMOOTHING_STEPS_MS, self.attack_ms, dir),
+            21 => self.release_ms = step(&SMOOTHING_STEPS_MS, self.release_ms, dir),
+            22 => self.gravity = step(&GRAVITY_STEPS, self.gravity, dir),
+            i if i >= FIRST_GRAPHIC_ITEM => {
+                let band = &mut self.graphic[i - FIRST_GRAPHIC_ITEM];
+                *band = (*band + dir as i8).clamp(-GRAPHIC_RANGE_DB, GRAPHIC_RANGE_DB);
+                // touching a slider means you want to hear it
+                self.eq = EqPreset::Graphic;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,136 @@
+// audio thread telemetry
+// the audio thread writes these, the render loop reads a `PlayerStats` snapshot
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use psp::sys;
+
+use crate::readahead::ReadaheadStats;
+
+/// Microsecond clock used for all timings
+pub fn now_us() -> u32 {
+    unsafe { sys::sceKernelGetSystemTimeLow() }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timing {
+    pub last_us: u32,
+    pub max_us: u32,
+    /// exponential moving average over roughly the last 16 buffers
+    pub avg_us: u32,
+}
+
+#[derive(Default)]
+pub(crate) struct TimingCounter {
+    last_us: AtomicU32,
+    max_us: AtomicU32,
+    avg_us: AtomicU32,
+}
+
+impl TimingCounter {
+    pub(crate) fn record(&self, us: u32) {
+        // only the audio thread writes, so load + store is fine here
+        let avg = self.avg_us.load(Ordering::Relaxed) as i64;
+        let avg = avg + (us as i64 - avg) / 16;
+        self.avg_us.store(avg as u32, Ordering::Relaxed);
+        self.last_us.store(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    /// Time `f` and record how long it took
+    pub(crate) fn time<T>(&self, f: impl FnOnce() -> T) -> T {
+        let start = now_us();
+        let r = f();
+        self.record(now_us().wrapping_sub(start));
+        r
+    }
+
+    fn snapshot(&self) -> Timing {
+        Timing {
+            last_us: self.last_us.load(Ordering::Relaxed),
+            max_us: self.max_us.load(Ordering::Relaxed),
+            avg_us: self.avg_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct PlayerCounters {
+    pub(crate) decode: TimingCounter,
+    pub(crate) fill: TimingCounter,
+    pub(crate) buffers: AtomicU32,
+    pub(crate) decode_errors: AtomicU32,
+    /// f32 bits, dB
+    pub(crate) gain_reduction: AtomicU32,
+}
+
+impl PlayerCounters {
+    pub(crate) fn snapshot(&self, io: Option<ReadaheadStats>) -> PlayerStats {
+        PlayerStats {
+            decode: self.decode.snapshot(),
+            fill: self.fill.snapshot(),
+            buffers: self.buffers.load(Ordering::Relaxed),
+            decode_errors: self.decode_errors.load(Ordering::Relaxed),
+            gain_reduction_db: f32::from_bits(self.gain_reduction.load(Ordering::Relaxed)),
+            io,
+        }
+    }
+}
+
+/// Snapshot of the audio thread's timings and counters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlayerStats {
+    /// time spent in `sceMp3Decode`
+    pub decode: Timing,
+    /// time spent in `fill_stream_buffer`
+    pub fill: Timing,
+    /// buffers handed to the mixer
+    pub buffers: u32,
+    pub decode_errors: u32,
+    /// how far the track limiter pulled the last buffer down, 0 or less
+    pub gain_reduction_db: f32,
+    /// readahead cache stats, when playing from a file
+    pub io: Option<ReadaheadStats>,
+}
+
+#[derive(Default)]
+pub(crate) struct MixerCounters {
+    pub(crate) output: TimingCounter,
+    pub(crate) buffers: AtomicU32,
+    pub(crate) late_buffers: AtomicU32,
+    pub(crate) underruns: AtomicU32,
+    pub(crate) voices: AtomicU32,
+    /// f32 bits, dB
+    pub(crate) gain_reduction: AtomicU32,
+}
+
+impl MixerCounters {
+    pub(crate) fn snapshot(&self) -> MixerStats {
+        MixerStats {
+            output: self.output.snapshot(),
+            buffers: self.buffers.load(Ordering::Relaxed),
+            late_buffers: self.late_buffers.load(Ordering::Relaxed),
+            underruns: self.underruns.load(Ordering::Relaxed),
+            voices: self.voices.load(Ordering::Relaxed),
+            gain_reduction_db: f32::from_bits(self.gain_reduction.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Snapshot of the mixer thread's timings and counters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MixerStats {
+    /// time blocked in `sceAudioSRCOutputBlocking`
+    pub output: Timing,
+    /// buffers handed to the SRC channel
+    pub buffers: u32,
+    /// buffers that were ready later than the previous one took to play,
+    /// i.e. the hardware queue probably ran dry (audible as a glitch)
+    pub late_buffers: u32,
+    /// times a voice had fewer frames ready than the mixer needed
+    pub underruns: u32,
+    /// voices currently playing
+    pub voices: u32,
+    /// how far the limiter pulled the last buffer down, 0 or less
+    pub gain_reduction_db: f32,
+}
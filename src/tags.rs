@@ -0,0 +1,322 @@
+// just enough tag parsing to find ReplayGain values
+// ID3v2 TXXX frames, APEv2 items and Vorbis comments
+
+extern crate alloc;
+use alloc::vec;
+
+use crate::error::Error;
+use crate::io::{ByteRead, ByteSeek, SeekFrom};
+
+// don't let a corrupt size field allocate half the heap
+const MAX_TAG_SIZE: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain_db: Option<f32>,
+    /// linear, 1.0 = full scale
+    pub track_peak: Option<f32>,
+    pub album_gain_db: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    /// Take values from `other` we don't have yet
+    fn merge(&mut self, other: ReplayGain) {
+        self.track_gain_db = self.track_gain_db.or(other.track_gain_db);
+        self.track_peak = self.track_peak.or(other.track_peak);
+        self.album_gain_db = self.album_gain_db.or(other.album_gain_db);
+        self.album_peak = self.album_peak.or(other.album_peak);
+    }
+
+    /// Handle one `KEY=value` pair, keys are case-insensitive
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        let Some(v) = parse_number(value) else {
+            return;
+        };
+        let key_is = |k: &str| key.eq_ignore_ascii_case(k.as_bytes());
+        if key_is("REPLAYGAIN_TRACK_GAIN") {
+            self.track_gain_db = Some(v);
+        } else if key_is("REPLAYGAIN_TRACK_PEAK") {
+            self.track_peak = Some(v);
+        } else if key_is("REPLAYGAIN_ALBUM_GAIN") {
+            self.album_gain_db = Some(v);
+        } else if key_is("REPLAYGAIN_ALBUM_PEAK") {
+            self.album_peak = Some(v);
+        }
+    }
+}
+
+/// Parse "-6.54 dB" / "0.988547" style values
+fn parse_number(value: &[u8]) -> Option<f32> {
+    let s = core::str::from_utf8(value).ok()?;
+    let s = s.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    let s = s
+        .strip_suffix("dB")
+        .or_else(|| s.strip_suffix("db"))
+        .unwrap_or(s)
+        .trim_end();
+    s.parse::<f32>().ok().filter(|v| v.is_finite())
+}
+
+fn u32_le(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn u32_be(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn synchsafe(b: &[u8]) -> u32 {
+    ((b[0] as u32 & 0x7F) << 21)
+        | ((b[1] as u32 & 0x7F) << 14)
+        | ((b[2] as u32 & 0x7F) << 7)
+        | (b[3] as u32 & 0x7F)
+}
+
+/// Read ReplayGain from every tag we understand, the first value found wins.
+/// Leaves the stream position undefined.
+pub fn read_replaygain<S: ByteRead + ByteSeek>(stream: &mut S) -> Result<ReplayGain, Error> {
+    let mut rg = read_id3v2(stream)?;
+    rg.merge(read_apev2(stream)?);
+    Ok(rg)
+}
+
+/// ID3v2.3/2.4 tag at the start of the file
+pub fn read_id3v2<S: ByteRead + ByteSeek>(stream: &mut S) -> Result<ReplayGain, Error> {
+    let mut rg = ReplayGain::default();
+
+    let mut header = [0u8; 10];
+    stream.seek(SeekFrom::Start(0))?;
+    if stream.read_full(&mut header)? < 10 || &header[0..3] != b"ID3" {
+        return Ok(rg);
+    }
+    let version = header[3];
+    let flags = header[5];
+    let size = synchsafe(&header[6..10]) as usize;
+    // v2.2 has 3 byte frame ids, unsynchronised tags would need undoing first
+    if !(3..=4).contains(&version) || flags & 0x80 != 0 || size > MAX_TAG_SIZE {
+        return Ok(rg);
+    }
+
+    let mut tag = vec![0u8; size];
+    let n = stream.read_full(&mut tag)?;
+    let tag = &tag[..n];
+
+    let mut pos = 0usize;
+    if flags & 0x40 != 0 && tag.len() >= 4 {
+        // extended header, v2.3 size excludes itself, v2.4 is synchsafe and includes it
+        pos = if version == 3 {
+            u32_be(&tag[0..4]) as usize + 4
+        } else {
+            synchsafe(&tag[0..4]) as usize
+        };
+    }
+
+    while pos + 10 <= tag.len() {
+        let id = &tag[pos..pos + 4];
+        if id[0] == 0 {
+            break; // padding
+        }
+        let frame_size = if version == 4 {
+            synchsafe(&tag[pos + 4..pos + 8])
+        } else {
+            u32_be(&tag[pos + 4..pos + 8])
+        } as usize;
+        let body_start = pos + 10;
+        let body_end = body_start.saturating_add(frame_size);
+        if body_end > tag.len() {
+            break;
+        }
+        if id == b"TXXX" {
+            parse_txxx(&tag[body_start..body_end], &mut rg);
+        }
+        pos = body_end;
+    }
+
+    Ok(rg)
+}
+
+/// TXXX: encoding byte, description, terminator, value
+fn parse_txxx(body: &[u8], rg: &mut ReplayGain) {
+    let Some((&encoding, text)) = body.split_first() else {
+        return;
+    };
+    let wide = encoding == 1 || encoding == 2;
+
+    // the terminator is two bytes for UTF-16
+    let split = if wide {
+        text.chunks_exact(2)
+            .position(|c| c == [0, 0])
+            .map(|i| (i * 2, i * 2 + 2))
+    } else {
+        text.iter().position(|&b| b == 0).map(|i| (i, i + 1))
+    };
+    let Some((desc_end, value_start)) = split else {
+        return;
+    };
+
+    let mut key = [0u8; 64];
+    let mut value = [0u8; 64];
+    let key_len = to_ascii(&text[..desc_end], encoding, &mut key);
+    let value_len = to_ascii(&text[value_start..], encoding, &mut value);
+    rg.set(&key[..key_len], &value[..value_len]);
+}
+
+/// Squash an ID3 text field down to ascii, everything we look for is ascii anyway
+fn to_ascii(field: &[u8], encoding: u8, out: &mut [u8]) -> usize {
+    let mut len = 0usize;
+    let mut push = |c: u8| {
+        if c != 0 && c.is_ascii() && len < out.len() {
+            out[len] = c;
+            len += 1;
+        }
+    };
+
+    if encoding == 1 || encoding == 2 {
+        // UTF-16 with BOM (1) or big endian without (2)
+        let mut big_endian = encoding == 2;
+        let mut body = field;
+        if body.len() >= 2 {
+            match (body[0], body[1]) {
+                (0xFF, 0xFE) => (big_endian, body) = (false, &body[2..]),
+                (0xFE, 0xFF) => (big_endian, body) = (true, &body[2..]),
+                _ => {}
+            }
+        }
+        for c in body.chunks_exact(2) {
+            let (lo, hi) = if big_endian {
+                (c[1], c[0])
+            } else {
+                (c[0], c[1])
+            };
+            if hi == 0 {
+                push(lo);
+            }
+        }
+    } else {
+        field.iter().for_each(|&c| push(c));
+    }
+
+    len
+}
+
+/// APEv2 tag at the end of the file (before an ID3v1 tag if there is one)
+pub fn read_apev2<S: ByteRead + ByteSeek>(stream: &mut S) -> Result<ReplayGain, Error> {
+    let mut rg = ReplayGain::default();
+    let size = stream.size()?;
+
+    let mut footer = [0u8; 32];
+    // try right at the end, then in front of a 128 byte ID3v1 tag
+    let mut footer_pos = None;
+    for skip in [0u64, 128] {
+        if size < 32 + skip {
+            continue;
+        }
+        stream.seek(SeekFrom::Start(size - 32 - skip))?;
+        if stream.read_full(&mut footer)? == 32 && &footer[0..8] == b"APETAGEX" {
+            footer_pos = Some(size - 32 - skip);
+            break;
+        }
+    }
+    let Some(footer_pos) = footer_pos else {
+        return Ok(rg);
+    };
+
+    // tag size covers the items and the footer
+    let tag_size = u32_le(&footer[12..16]) as usize;
+    let item_count = u32_le(&footer[16..20]) as usize;
+    if !(32..=MAX_TAG_SIZE).contains(&tag_size) || tag_size as u64 - 32 > footer_pos {
+        return Ok(rg);
+    }
+
+    let items_len = tag_size - 32;
+    let mut items = vec![0u8; items_len];
+    stream.seek(SeekFrom::Start(footer_pos - items_len as u64))?;
+    let n = stream.read_full(&mut items)?;
+    parse_ape_items(&items[..n], item_count, &mut rg);
+
+    Ok(rg)
+}
+
+fn parse_ape_items(items: &[u8], count: usize, rg: &mut ReplayGain) {
+    let mut pos = 0usize;
+    for _ in 0..count {
+        if pos + 8 > items.len() {
+            break;
+        }
+        let value_len = u32_le(&items[pos..pos + 4]) as usize;
+        let flags = u32_le(&items[pos + 4..pos + 8]);
+        let key_start = pos + 8;
+        let Some(key_len) = items[key_start..].iter().position(|&b| b == 0) else {
+            break;
+        };
+        let value_start = key_start + key_len + 1;
+        let value_end = value_start.saturating_add(value_len);
+        if value_end > items.len() {
+            break;
+        }
+        // bits 1-2 are the item type, 0 = utf-8 text
+        if (flags >> 1) & 3 == 0 {
+            rg.set(
+                &items[key_start..key_start + key_len],
+                &items[value_start..value_end],
+            );
+        }
+        pos = value_end;
+    }
+}
+
+/// Vorbis comment block (as found in Ogg and FLAC), starting at the vendor length.
+/// Nothing feeds this yet since we only decode MP3.
+#[allow(dead_code)]
+pub fn read_vorbis_comments<R: ByteRead>(reader: &mut R) -> Result<ReplayGain, Error> {
+    let mut rg = ReplayGain::default();
+    let mut len_buf = [0u8; 4];
+
+    let read_len = |reader: &mut R, buf: &mut [u8; 4]| -> Result<Option<usize>, Error> {
+        Ok((reader.read_full(buf)? == 4).then(|| u32_le(buf) as usize))
+    };
+
+    let Some(vendor_len) = read_len(reader, &mut len_buf)? else {
+        return Ok(rg);
+    };
+    skip(reader, vendor_len)?;
+
+    let Some(count) = read_len(reader, &mut len_buf)? else {
+        return Ok(rg);
+    };
+
+    let mut comment = [0u8; 128];
+    for _ in 0..count {
+        let Some(len) = read_len(reader, &mut len_buf)? else {
+            break;
+        };
+        // replaygain comments are short, skip anything that isn't (cover art etc)
+        if len > comment.len() {
+            skip(reader, len)?;
+            continue;
+        }
+        if reader.read_full(&mut comment[..len])? < len {
+            break;
+        }
+        let c = &comment[..len];
+        if let Some(eq) = c.iter().position(|&b| b == b'=') {
+            rg.set(&c[..eq], &c[eq + 1..]);
+        }
+    }
+
+    Ok(rg)
+}
+
+fn skip<R: ByteRead>(reader: &mut R, mut n: usize) -> Result<(), Error> {
+    let mut scratch = [0u8; 256];
+    while n > 0 {
+        let chunk = n.min(scratch.len());
+        let read = reader.read(&mut scratch[..chunk])?;
+        if read == 0 {
+            break;
+        }
+        n -= read;
+    }
+    Ok(())
+}
@@ -3,40 +3,51 @@ use core::ffi::c_void;
 use alloc::vec::Vec;
 use psp::sys::{self, SceUid};
 
+use crate::error::Error;
+use crate::io::{ByteRead, ByteSeek, SeekFrom};
+
 pub struct AssetStream {
     fd: SceUid,
     path_z: Vec<u8>,
 }
 
 impl AssetStream {
-    pub fn open(path: &str) -> Result<Self, i32> {
+    pub fn open(path: &str) -> Result<Self, Error> {
         let path_z = to_c_path(path);
         let fd = unsafe { sys::sceIoOpen(path_z.as_ptr(), sys::IoOpenFlags::RD_ONLY, 0) };
         if fd.0 < 0 {
-            Err(fd.0)
+            Err(Error::Io(fd.0))
         } else {
             Ok(Self { fd, path_z })
         }
     }
+}
 
-    /// Read up to out.len() bytes into out
-    pub fn read(&mut self, out: &mut [u8]) -> Result<usize, i32> {
+impl ByteRead for AssetStream {
+    fn read(&mut self, out: &mut [u8]) -> Result<usize, Error> {
         let r =
             unsafe { sys::sceIoRead(self.fd, out.as_mut_ptr() as *mut c_void, out.len() as u32) };
-        if r < 0 { Err(r) } else { Ok(r as usize) }
+        if r < 0 {
+            Err(Error::Io(r))
+        } else {
+            Ok(r as usize)
+        }
     }
+}
 
-    pub fn seek(&mut self, offset: i64, whence: sys::IoWhence) -> Result<i64, i32> {
+impl ByteSeek for AssetStream {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let (offset, whence) = match pos {
+            SeekFrom::Start(off) => (off as i64, sys::IoWhence::Set),
+            SeekFrom::End(off) => (off, sys::IoWhence::End),
+            SeekFrom::Current(off) => (off, sys::IoWhence::Cur),
+        };
         let pos = unsafe { sys::sceIoLseek(self.fd, offset, whence) };
-        if pos < 0 { Err(pos as i32) } else { Ok(pos) }
-    }
-
-    /// Get file size without changing the current position.
-    pub fn size(&mut self) -> Result<i64, i32> {
-        let cur = self.seek(0, sys::IoWhence::Cur)?;
-        let end = self.seek(0, sys::IoWhence::End)?;
-        let _ = self.seek(cur, sys::IoWhence::Set)?;
-        Ok(end)
+        if pos < 0 {
+            Err(Error::Io(pos as i32))
+        } else {
+            Ok(pos as u64)
+        }
     }
 }
 
@@ -57,36 +68,8 @@ pub fn to_c_path(path: &str) -> Vec<u8> {
 /// will replace include_bytes! usage
 /// load_asset should ideally be replaced with `AssetStream` for larger files
 pub fn load_asset(path: &str, buffer: &mut [u8]) -> Option<usize> {
-    let c_path = to_c_path(path);
-
-    let fd = unsafe { sys::sceIoOpen(c_path.as_ptr(), sys::IoOpenFlags::RD_ONLY, 0) };
-    if fd.0 < 0 {
-        return None;
-    }
-
-    let mut total = 0usize;
-
-    while total < buffer.len() {
-        let remaining = (buffer.len() - total) as u32;
-
-        let r =
-            unsafe { sys::sceIoRead(fd, buffer[total..].as_mut_ptr() as *mut c_void, remaining) };
-
-        if r < 0 {
-            // read error
-            unsafe { sys::sceIoClose(fd) };
-            return None;
-        }
-        if r == 0 {
-            // EOF
-            break;
-        }
-
-        total += r as usize;
-    }
-
-    unsafe { sys::sceIoClose(fd) };
-    Some(total)
+    let mut stream = AssetStream::open(path).ok()?;
+    stream.read_full(buffer).ok()
 }
 
 /// round up to the next power of 2 (for texture dimensions)
@@ -0,0 +1,77 @@
+// length of an mp3 from the Xing/Info or VBRI header in its first frame,
+// the bitrate of one frame says nothing about the rest of a VBR file
+
+/// How far past the tags to look for the first frame
+pub const SCAN_LEN: usize = 4096;
+
+/// Layer III frame header fields we need
+struct FrameHeader {
+    mpeg1: bool,
+    mono: bool,
+}
+
+impl FrameHeader {
+    fn parse(b: &[u8]) -> Option<Self> {
+        if b.len() < 4 || b[0] != 0xFF || b[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+        let version = (b[1] >> 3) & 3;
+        let layer = (b[1] >> 1) & 3;
+        let bitrate = b[2] >> 4;
+        let rate = (b[2] >> 2) & 3;
+        // 1 is a reserved version, layer bits 01 are layer III
+        if version == 1 || layer != 1 || bitrate == 0 || bitrate == 15 || rate == 3 {
+            return None;
+        }
+        Some(Self {
+            mpeg1: version == 3,
+            mono: b[3] >> 6 == 3,
+        })
+    }
+
+    fn samples_per_frame(&self) -> u32 {
+        if self.mpeg1 { 1152 } else { 576 }
+    }
+
+    /// Where a Xing/Info header starts, right after the side info
+    fn xing_offset(&self) -> usize {
+        4 + match (self.mpeg1, self.mono) {
+            (true, false) => 32,
+            (true, true) | (false, false) => 17,
+            (false, true) => 9,
+        }
+    }
+}
+
+fn u32_be(b: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(b.get(..4)?.try_into().ok()?))
+}
+
+/// Frame count from a Xing/Info or VBRI header at the start of `frame`
+fn header_frames(frame: &[u8], header: &FrameHeader) -> Option<u32> {
+    let xing = frame.get(header.xing_offset()..)?;
+    if xing.starts_with(b"Xing") || xing.starts_with(b"Info") {
+        let flags = u32_be(xing.get(4..)?)?;
+        // bit 0: the frame count field is there
+        return if flags & 1 != 0 {
+            u32_be(xing.get(8..)?)
+        } else {
+            None
+        };
+    }
+    // always 32 bytes after the header, whatever the version and channels
+    let vbri = frame.get(36..)?;
+    if vbri.starts_with(b"VBRI") {
+        return u32_be(vbri.get(14..)?);
+    }
+    None
+}
+
+/// PCM frames in the stream if the first mp3 frame in `data` says how many
+/// mp3 frames there are. `data` starts where the audio does, after any tags.
+pub fn total_frames(data: &[u8]) -> Option<u32> {
+    let start = (0..data.len()).find(|&i| FrameHeader::parse(&data[i..]).is_some())?;
+    let header = FrameHeader::parse(&data[start..])?;
+    let frames = header_frames(&data[start..], &header)?;
+    frames.checked_mul(header.samples_per_frame())
+}